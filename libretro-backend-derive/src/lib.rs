@@ -0,0 +1,299 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{Data, DeriveInput, Expr, Fields, Ident, LitStr, Token, Type};
+use syn::parse::Parse;
+
+struct OptionAttributes {
+    key: Option< String >,
+    description: Option< String >,
+    values: Vec< String >,
+    default: Option< String >
+}
+
+fn parse_prefix( input: &DeriveInput ) -> syn::Result< String > {
+    let mut prefix = String::new();
+    for attribute in &input.attrs {
+        if attribute.path().is_ident( "options" ) == false {
+            continue;
+        }
+
+        attribute.parse_nested_meta( |meta| {
+            if meta.path.is_ident( "prefix" ) {
+                prefix = meta.value()?.parse::< LitStr >()?.value();
+                Ok(())
+            } else {
+                Err( meta.error( "unknown options attribute; expected `prefix`" ) )
+            }
+        })?;
+    }
+
+    Ok( prefix )
+}
+
+fn parse_option_attributes( field: &syn::Field ) -> syn::Result< OptionAttributes > {
+    let mut output = OptionAttributes {
+        key: None,
+        description: None,
+        values: Vec::new(),
+        default: None
+    };
+
+    for attribute in &field.attrs {
+        if attribute.path().is_ident( "option" ) == false {
+            continue;
+        }
+
+        attribute.parse_nested_meta( |meta| {
+            if meta.path.is_ident( "key" ) {
+                output.key = Some( meta.value()?.parse::< LitStr >()?.value() );
+            } else if meta.path.is_ident( "description" ) {
+                output.description = Some( meta.value()?.parse::< LitStr >()?.value() );
+            } else if meta.path.is_ident( "default" ) {
+                output.default = Some( meta.value()?.parse::< LitStr >()?.value() );
+            } else if meta.path.is_ident( "values" ) {
+                let input = meta.value()?;
+                let content;
+                syn::bracketed!( content in input );
+                let values = content.parse_terminated( < LitStr as Parse >::parse, Token![,] )?;
+                output.values.extend( values.iter().map( |value| value.value() ) );
+            } else {
+                return Err( meta.error( "unknown option attribute; expected one of `key`, `description`, `values` or `default`" ) );
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok( output )
+}
+
+fn is_bool( kind: &Type ) -> bool {
+    match *kind {
+        Type::Path( ref path ) => path.qself.is_none() && path.path.is_ident( "bool" ),
+        _ => false
+    }
+}
+
+fn expand( input: DeriveInput ) -> syn::Result< proc_macro2::TokenStream > {
+    let fields = match input.data {
+        Data::Struct( ref data ) => match data.fields {
+            Fields::Named( ref fields ) => &fields.named,
+            _ => return Err( syn::Error::new_spanned( &input.ident, "CoreOptions can only be derived for structs with named fields" ) )
+        },
+        _ => return Err( syn::Error::new_spanned( &input.ident, "CoreOptions can only be derived for structs" ) )
+    };
+
+    let prefix = parse_prefix( &input )?;
+    let mut declarations = Vec::new();
+    let mut loaders = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let attributes = parse_option_attributes( field )?;
+
+        let key = attributes.key.unwrap_or_else( || format!( "{}{}", prefix, ident ) );
+        let description = match attributes.description {
+            Some( description ) => description,
+            None => return Err( syn::Error::new_spanned( ident, "missing `#[option(description = \"...\")]`" ) )
+        };
+
+        let mut values = attributes.values;
+        if values.is_empty() {
+            if is_bool( &field.ty ) {
+                values.push( "disabled".to_owned() );
+                values.push( "enabled".to_owned() );
+            } else {
+                return Err( syn::Error::new_spanned( ident, "missing `#[option(values = [...])]`" ) );
+            }
+        }
+
+        let default = match attributes.default {
+            Some( default ) => {
+                if values.contains( &default ) == false {
+                    return Err( syn::Error::new_spanned( ident, "the default value must be one of the declared values" ) );
+                }
+                quote! { .default_value( #default ) }
+            },
+            None => quote! {}
+        };
+
+        let key = LitStr::new( &key, Span::call_site() );
+        let description = LitStr::new( &description, Span::call_site() );
+        declarations.push( quote! {
+            ::libretro_backend::options::CoreOption::new( #key, #description )
+                #( .value( #values ) )*
+                #default
+        });
+
+        let kind = &field.ty;
+        loaders.push( quote! {
+            if let Some( value ) = ::libretro_backend::options::get_as::< #kind >( #key ) {
+                self.#ident = value;
+            }
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok( quote! {
+        impl #impl_generics ::libretro_backend::options::CoreOptions for #name #ty_generics #where_clause {
+            fn options() -> ::std::vec::Vec< ::libretro_backend::options::CoreOption > {
+                vec![ #( #declarations ),* ]
+            }
+
+            fn load_from_frontend( &mut self ) {
+                #( #loaders )*
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(CoreOptions, attributes(options, option))]
+pub fn derive_core_options( input: TokenStream ) -> TokenStream {
+    let input = syn::parse_macro_input!( input as DeriveInput );
+    match expand( input ) {
+        Ok( output ) => output.into(),
+        Err( error ) => compile_errors( error, quote! {} ).into()
+    }
+}
+
+// `syn::Error::to_compile_error` refers to `::core`, which
+// doesn't resolve in crates using the 2015 edition. The item
+// itself is kept so that its uses don't cause errors of their own.
+fn compile_errors( error: syn::Error, item: proc_macro2::TokenStream ) -> proc_macro2::TokenStream {
+    let errors = error.into_iter().map( |error| {
+        let message = error.to_string();
+        quote_spanned! { error.span() => compile_error!( #message ); }
+    });
+
+    quote! {
+        #item
+        #( #errors )*
+    }
+}
+
+// Everything `libretro_core!` exports, except for `retro_init`
+// and `retro_deinit` which own the core's instance.
+const ENTRY_POINTS: &'static [&'static str] = &[
+    "retro_api_version",
+    "retro_set_environment",
+    "retro_set_video_refresh",
+    "retro_set_audio_sample",
+    "retro_set_audio_sample_batch",
+    "retro_set_input_poll",
+    "retro_set_input_state",
+    "retro_get_system_info",
+    "retro_get_system_av_info",
+    "retro_set_controller_port_device",
+    "retro_reset",
+    "retro_run",
+    "retro_serialize_size",
+    "retro_serialize",
+    "retro_unserialize",
+    "retro_cheat_reset",
+    "retro_cheat_set",
+    "retro_load_game",
+    "retro_load_game_special",
+    "retro_unload_game",
+    "retro_get_region",
+    "retro_get_memory_data",
+    "retro_get_memory_size"
+];
+
+struct CoreAttributes {
+    constructor: Option< Expr >,
+    prefix: Option< LitStr >,
+    skip: Vec< Ident >
+}
+
+fn parse_core_attributes( attributes: TokenStream ) -> syn::Result< CoreAttributes > {
+    let mut output = CoreAttributes {
+        constructor: None,
+        prefix: None,
+        skip: Vec::new()
+    };
+
+    let parser = syn::meta::parser( |meta| {
+        if meta.path.is_ident( "constructor" ) {
+            output.constructor = Some( meta.value()?.parse::< Expr >()? );
+            Ok(())
+        } else if meta.path.is_ident( "prefix" ) {
+            output.prefix = Some( meta.value()?.parse::< LitStr >()? );
+            Ok(())
+        } else if meta.path.is_ident( "skip" ) {
+            meta.parse_nested_meta( |entry| {
+                let name = entry.path.require_ident()?;
+                if name == "retro_init" || name == "retro_deinit" {
+                    return Err( entry.error( "`retro_init` and `retro_deinit` can't be skipped since they own the core's instance" ) );
+                }
+
+                if ENTRY_POINTS.iter().any( |entry_point| name == entry_point ) == false {
+                    return Err( entry.error( format!( "unknown entry point `{}`", name ) ) );
+                }
+
+                output.skip.push( name.clone() );
+                Ok(())
+            })
+        } else {
+            Err( meta.error( "unknown libretro_core attribute; expected `constructor`, `prefix` or `skip`" ) )
+        }
+    });
+
+    syn::parse::Parser::parse( parser, attributes )?;
+    Ok( output )
+}
+
+fn expand_core( attributes: CoreAttributes, input: DeriveInput ) -> syn::Result< proc_macro2::TokenStream > {
+    if input.generics.params.is_empty() == false {
+        return Err( syn::Error::new_spanned( &input.generics, "#[libretro_core] can't be used on generic types; use `libretro_core!` with a concrete type instead" ) );
+    }
+
+    let name = &input.ident;
+    let constructor = match attributes.constructor {
+        Some( constructor ) => quote! { #constructor },
+        None => quote_spanned! { name.span() => <#name as ::std::default::Default>::default() }
+    };
+
+    let prefix = attributes.prefix.unwrap_or_else( || LitStr::new( "", Span::call_site() ) );
+    let skip = attributes.skip;
+    let entry_points = ENTRY_POINTS.iter()
+        .filter( |entry_point| skip.iter().any( |skipped| skipped == *entry_point ) == false )
+        .map( |entry_point| Ident::new( entry_point, Span::call_site() ) );
+
+    // The exported symbols are unmangled anyway, so everything
+    // else can be kept out of the user's namespace.
+    Ok( quote! {
+        #input
+
+        const _: () = {
+            ::libretro_backend::libretro_core!( @instance #name );
+            ::libretro_backend::libretro_core!( @entry retro_init, #name => #constructor, prefix = #prefix );
+            ::libretro_backend::libretro_core!( @entry retro_deinit, #name, prefix = #prefix );
+            #( ::libretro_backend::libretro_core!( @entry #entry_points, #name, prefix = #prefix ); )*
+        };
+    })
+}
+
+// Generates the same symbols as `libretro_core!`; the ones listed in
+// `skip( ... )` are left out, and have to be exported by the core itself
+// since frontends refuse to load cores which are missing any of them.
+#[proc_macro_attribute]
+pub fn libretro_core( attributes: TokenStream, input: TokenStream ) -> TokenStream {
+    let item = proc_macro2::TokenStream::from( input.clone() );
+    let attributes = match parse_core_attributes( attributes ) {
+        Ok( attributes ) => attributes,
+        Err( error ) => return compile_errors( error, item ).into()
+    };
+
+    let input = syn::parse_macro_input!( input as DeriveInput );
+    match expand_core( attributes, input ) {
+        Ok( output ) => output.into(),
+        Err( error ) => compile_errors( error, item ).into()
+    }
+}
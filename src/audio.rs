@@ -0,0 +1,111 @@
+use std::cmp::min;
+
+use audio_queue::AudioQueue;
+#[cfg(feature = "resampler")]
+use resampler;
+use {RuntimeHandle, UploadError};
+
+pub(crate) const SILENT_AUDIO_SAMPLE_RATE: f64 = 44100.0;
+const MAX_AUDIO_BATCH_FRAMES: usize = 4096;
+
+// Has to be even so that the chunks don't split stereo frames.
+pub(crate) const AUDIO_CONVERSION_CHUNK_SIZE: usize = 1024;
+
+// What to do when the core uploads less audio in a frame
+// than the declared sample rate requires.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum AudioPacing {
+    Strict,
+    Warn,
+    PadWithSilence,
+    Disabled
+}
+
+impl RuntimeHandle {
+    pub fn upload_audio_frame( &mut self, data: &[i16] ) -> Result< (), UploadError > {
+        if data.len() % 2 != 0 {
+            return Err( UploadError::NotStereo );
+        }
+
+        // The frontend is free to consume less than it was given,
+        // so keep feeding it until everything's gone.
+        let mut data = data;
+        while data.is_empty() == false {
+            let frames = min( data.len() / 2, MAX_AUDIO_BATCH_FRAMES );
+            let consumed = unsafe {
+                (self.audio_sample_batch_callback)( data.as_ptr(), frames )
+            };
+
+            if consumed == 0 {
+                return Err( UploadError::FrontendStalled { dropped_samples: data.len() } );
+            }
+
+            let consumed = min( consumed, frames ) * 2;
+            self.audio_samples_uploaded += consumed;
+            data = &data[ consumed.. ];
+        }
+
+        Ok(())
+    }
+
+    // Samples are expected to be in the -1.0..1.0 range; anything
+    // outside of it gets clamped.
+    pub fn upload_audio_frame_f32( &mut self, data: &[f32] ) -> Result< (), UploadError > {
+        if data.len() % 2 != 0 {
+            return Err( UploadError::NotStereo );
+        }
+
+        let mut buffer = [0_i16; AUDIO_CONVERSION_CHUNK_SIZE];
+        for chunk in data.chunks( AUDIO_CONVERSION_CHUNK_SIZE ) {
+            let buffer = &mut buffer[ ..chunk.len() ];
+            for (output, &sample) in buffer.iter_mut().zip( chunk ) {
+                // Float to int casts saturate, so this is a plain loop
+                // the compiler is free to vectorize.
+                *output = (sample * 32767.0) as i16;
+            }
+
+            self.upload_audio_frame( buffer )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn upload_audio_frame_mono( &mut self, data: &[i16] ) -> Result< (), UploadError > {
+        let mut buffer = [0_i16; AUDIO_CONVERSION_CHUNK_SIZE];
+        for chunk in data.chunks( AUDIO_CONVERSION_CHUNK_SIZE / 2 ) {
+            let buffer = &mut buffer[ ..chunk.len() * 2 ];
+            for (output, &sample) in buffer.chunks_mut( 2 ).zip( chunk ) {
+                output[ 0 ] = sample;
+                output[ 1 ] = sample;
+            }
+
+            self.upload_audio_frame( buffer )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn drain_audio( &mut self, queue: &AudioQueue ) -> Result< (), UploadError > {
+        let mut buffer = [0_i16; AUDIO_CONVERSION_CHUNK_SIZE];
+        loop {
+            let count = queue.pop( &mut buffer );
+            if count == 0 {
+                break;
+            }
+
+            self.upload_audio_frame( &buffer[ ..count ] )?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "resampler")]
+    pub fn upload_audio_frame_resampled( &mut self, resampler: &mut resampler::Resampler, data: &[i16] ) -> Result< (), UploadError > {
+        let output = resampler.process( data );
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        self.upload_audio_frame( output )
+    }
+}
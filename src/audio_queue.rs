@@ -0,0 +1,168 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// A single producer, single consumer ring buffer of interleaved stereo
+// samples; the emulation thread pushes into it and `RuntimeHandle::drain_audio`
+// uploads whatever has accumulated from within `on_run`.
+pub struct AudioQueue {
+    buffer: Box< [UnsafeCell< i16 >] >,
+    read_position: AtomicUsize,
+    write_position: AtomicUsize,
+    is_pushing: AtomicBool,
+    is_popping: AtomicBool
+}
+
+unsafe impl Send for AudioQueue {}
+unsafe impl Sync for AudioQueue {}
+
+struct Exclusive< 'a >( &'a AtomicBool );
+
+impl< 'a > Exclusive< 'a > {
+    fn acquire( flag: &'a AtomicBool, message: &str ) -> Self {
+        if flag.compare_exchange( false, true, Ordering::Acquire, Ordering::Relaxed ).is_err() {
+            panic!( "{}", message );
+        }
+
+        Exclusive( flag )
+    }
+}
+
+impl< 'a > Drop for Exclusive< 'a > {
+    fn drop( &mut self ) {
+        self.0.store( false, Ordering::Release );
+    }
+}
+
+impl AudioQueue {
+    // The capacity is in samples and gets rounded up to a power of two.
+    pub fn new( capacity: usize ) -> AudioQueue {
+        let capacity = capacity.max( 2 ).next_power_of_two();
+        AudioQueue {
+            buffer: (0..capacity).map( |_| UnsafeCell::new( 0 ) ).collect::< Vec< _ > >().into_boxed_slice(),
+            read_position: AtomicUsize::new( 0 ),
+            write_position: AtomicUsize::new( 0 ),
+            is_pushing: AtomicBool::new( false ),
+            is_popping: AtomicBool::new( false )
+        }
+    }
+
+    pub fn capacity( &self ) -> usize {
+        self.buffer.len()
+    }
+
+    // Only a snapshot when called from neither the producer nor the consumer.
+    pub fn len( &self ) -> usize {
+        // The read position never passes the write position, so it has to be loaded first.
+        let read_position = self.read_position.load( Ordering::Acquire );
+        let write_position = self.write_position.load( Ordering::Acquire );
+        write_position.wrapping_sub( read_position ).min( self.buffer.len() )
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.len() == 0
+    }
+
+    // Returns how many samples were actually queued; whatever
+    // doesn't fit is dropped.
+    pub fn push( &self, data: &[i16] ) -> usize {
+        assert!( data.len() % 2 == 0, "Audio data must be in stereo!" );
+        let _guard = Exclusive::acquire( &self.is_pushing, "AudioQueue can only have one producer at a time!" );
+
+        let mask = self.buffer.len() - 1;
+        let read_position = self.read_position.load( Ordering::Acquire );
+        let write_position = self.write_position.load( Ordering::Relaxed );
+        let free = self.buffer.len() - write_position.wrapping_sub( read_position );
+        let count = free.min( data.len() ) & !1;
+        for (offset, &sample) in data[ ..count ].iter().enumerate() {
+            unsafe {
+                *self.buffer[ write_position.wrapping_add( offset ) & mask ].get() = sample;
+            }
+        }
+
+        self.write_position.store( write_position.wrapping_add( count ), Ordering::Release );
+        count
+    }
+
+    pub fn pop( &self, output: &mut [i16] ) -> usize {
+        let _guard = Exclusive::acquire( &self.is_popping, "AudioQueue can only have one consumer at a time!" );
+
+        let mask = self.buffer.len() - 1;
+        let write_position = self.write_position.load( Ordering::Acquire );
+        let read_position = self.read_position.load( Ordering::Relaxed );
+        let available = write_position.wrapping_sub( read_position );
+        let count = available.min( output.len() ) & !1;
+        for (offset, sample) in output[ ..count ].iter_mut().enumerate() {
+            unsafe {
+                *sample = *self.buffer[ read_position.wrapping_add( offset ) & mask ].get();
+            }
+        }
+
+        self.read_position.store( read_position.wrapping_add( count ), Ordering::Release );
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_is_rounded_up() {
+        assert_eq!( AudioQueue::new( 0 ).capacity(), 2 );
+        assert_eq!( AudioQueue::new( 6 ).capacity(), 8 );
+        assert_eq!( AudioQueue::new( 8 ).capacity(), 8 );
+    }
+
+    #[test]
+    fn push_drops_what_doesnt_fit() {
+        let queue = AudioQueue::new( 8 );
+        assert_eq!( queue.push( &[1, 2, 3, 4, 5, 6] ), 6 );
+        assert_eq!( queue.push( &[7, 8, 9, 10] ), 2 );
+        assert_eq!( queue.len(), 8 );
+
+        let mut output = [0; 10];
+        assert_eq!( queue.pop( &mut output ), 8 );
+        assert_eq!( output, [1, 2, 3, 4, 5, 6, 7, 8, 0, 0] );
+        assert!( queue.is_empty() );
+    }
+
+    #[test]
+    fn wraps_around() {
+        let queue = AudioQueue::new( 8 );
+        let mut output = [0; 6];
+        for round in 0..10 {
+            let data = [round, round + 1, round + 2, round + 3, round + 4, round + 5];
+            assert_eq!( queue.push( &data ), 6 );
+            assert_eq!( queue.len(), 6 );
+            assert_eq!( queue.pop( &mut output ), 6 );
+            assert_eq!( output, data );
+        }
+    }
+
+    #[test]
+    fn wraps_around_the_position_counters() {
+        let queue = AudioQueue::new( 4 );
+        queue.read_position.store( usize::MAX - 1, Ordering::Relaxed );
+        queue.write_position.store( usize::MAX - 1, Ordering::Relaxed );
+
+        assert_eq!( queue.push( &[1, 2, 3, 4] ), 4 );
+        assert_eq!( queue.len(), 4 );
+        assert_eq!( queue.push( &[5, 6] ), 0 );
+
+        let mut output = [0; 4];
+        assert_eq!( queue.pop( &mut output[ ..2 ] ), 2 );
+        assert_eq!( queue.push( &[5, 6] ), 2 );
+        assert_eq!( queue.pop( &mut output ), 4 );
+        assert_eq!( output, [3, 4, 5, 6] );
+    }
+
+    #[test]
+    fn only_whole_frames_are_popped() {
+        let queue = AudioQueue::new( 8 );
+        queue.push( &[1, 2, 3, 4] );
+
+        let mut output = [0; 3];
+        assert_eq!( queue.pop( &mut output ), 2 );
+        assert_eq!( queue.len(), 2 );
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CheatCode {
+    pub address: u32,
+    pub value: u8,
+    // The value is only written if the memory currently contains this.
+    pub compare: Option< u8 >
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct InvalidCheatCode {
+    code: String
+}
+
+impl InvalidCheatCode {
+    fn new( code: &str ) -> InvalidCheatCode {
+        InvalidCheatCode {
+            code: code.to_owned()
+        }
+    }
+}
+
+impl fmt::Display for InvalidCheatCode {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        write!( formatter, "invalid cheat code: '{}'", self.code )
+    }
+}
+
+impl error::Error for InvalidCheatCode {}
+
+fn parse_hex( string: &str, code: &str ) -> Result< u32, InvalidCheatCode > {
+    u32::from_str_radix( string.trim(), 16 ).map_err( |_| InvalidCheatCode::new( code ) )
+}
+
+fn parse_byte( string: &str, code: &str ) -> Result< u8, InvalidCheatCode > {
+    let value = parse_hex( string, code )?;
+    if value > 0xFF {
+        return Err( InvalidCheatCode::new( code ) );
+    }
+
+    Ok( value as u8 )
+}
+
+// Parses hexadecimal `address:value` or `address:compare:value` codes;
+// several of them can be joined together with a `+`, e.g. `C0A5:63+C0A6:01`.
+pub fn parse_raw( codes: &str ) -> Result< Vec< CheatCode >, InvalidCheatCode > {
+    let mut output = Vec::new();
+    for code in codes.split( '+' ).map( |code| code.trim() ).filter( |code| code.is_empty() == false ) {
+        let parts: Vec< &str > = code.split( ':' ).collect();
+        let cheat = match parts.len() {
+            2 => CheatCode {
+                address: parse_hex( parts[ 0 ], code )?,
+                value: parse_byte( parts[ 1 ], code )?,
+                compare: None
+            },
+            3 => CheatCode {
+                address: parse_hex( parts[ 0 ], code )?,
+                value: parse_byte( parts[ 2 ], code )?,
+                compare: Some( parse_byte( parts[ 1 ], code )? )
+            },
+            _ => return Err( InvalidCheatCode::new( code ) )
+        };
+
+        output.push( cheat );
+    }
+
+    Ok( output )
+}
+
+const NES_GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+fn hex_digits( code: &str, stripped: &str ) -> Result< Vec< u32 >, InvalidCheatCode > {
+    stripped.chars().map( |digit| digit.to_digit( 16 ).ok_or_else( || InvalidCheatCode::new( code ) ) ).collect()
+}
+
+fn strip_separators( code: &str ) -> String {
+    code.trim().chars().filter( |&character| character != '-' && character != ' ' ).collect()
+}
+
+// Six or eight letters, e.g. `SXIOPO`; the address is in the cartridge's
+// ROM area, so it has to be applied by the core when the ROM is read.
+pub fn parse_nes_game_genie( code: &str ) -> Result< CheatCode, InvalidCheatCode > {
+    let stripped = strip_separators( code ).to_ascii_uppercase();
+    let n: Vec< u32 > = stripped.chars().map( |letter| {
+        NES_GAME_GENIE_LETTERS.find( letter ).map( |index| index as u32 ).ok_or_else( || InvalidCheatCode::new( code ) )
+    }).collect::< Result< _, _ > >()?;
+
+    if n.len() != 6 && n.len() != 8 {
+        return Err( InvalidCheatCode::new( code ) );
+    }
+
+    let address =
+        0x8000 |
+        ((n[ 3 ] & 7) << 12) |
+        ((n[ 5 ] & 7) << 8) | ((n[ 4 ] & 8) << 8) |
+        ((n[ 2 ] & 7) << 4) | ((n[ 1 ] & 8) << 4) |
+        (n[ 4 ] & 7) | (n[ 3 ] & 8);
+
+    let (value, compare) = if n.len() == 6 {
+        (((n[ 1 ] & 7) << 4) | ((n[ 0 ] & 8) << 4) | (n[ 0 ] & 7) | (n[ 5 ] & 8), None)
+    } else {
+        let value = ((n[ 1 ] & 7) << 4) | ((n[ 0 ] & 8) << 4) | (n[ 0 ] & 7) | (n[ 7 ] & 8);
+        let compare = ((n[ 7 ] & 7) << 4) | ((n[ 6 ] & 8) << 4) | (n[ 6 ] & 7) | (n[ 5 ] & 8);
+        (value, Some( compare as u8 ))
+    };
+
+    Ok( CheatCode {
+        address: address,
+        value: value as u8,
+        compare: compare
+    })
+}
+
+// `VVA-AAA` or `VVA-AAA-CCC`, e.g. `00A-17B-C49`; as with the NES
+// the address is in ROM, so it's up to the core to apply it.
+pub fn parse_game_boy_game_genie( code: &str ) -> Result< CheatCode, InvalidCheatCode > {
+    let stripped = strip_separators( code );
+    let d = hex_digits( code, &stripped )?;
+    if d.len() != 6 && d.len() != 9 {
+        return Err( InvalidCheatCode::new( code ) );
+    }
+
+    let value = (d[ 0 ] << 4) | d[ 1 ];
+    let address = ((d[ 5 ] ^ 0xF) << 12) | (d[ 2 ] << 8) | (d[ 3 ] << 4) | d[ 4 ];
+    let compare = if d.len() == 9 {
+        // The middle digit is only a checksum.
+        let compare = ((d[ 6 ] << 4) | d[ 8 ]) as u8;
+        Some( compare.rotate_right( 2 ) ^ 0xBA )
+    } else {
+        None
+    };
+
+    Ok( CheatCode {
+        address: address,
+        value: value as u8,
+        compare: compare
+    })
+}
+
+// `TTVVLLHH`, e.g. `01FF16D0`; the address is little endian and
+// the leading external RAM bank number is ignored.
+pub fn parse_game_boy_gameshark( code: &str ) -> Result< CheatCode, InvalidCheatCode > {
+    let stripped = strip_separators( code );
+    let d = hex_digits( code, &stripped )?;
+    if d.len() != 8 {
+        return Err( InvalidCheatCode::new( code ) );
+    }
+
+    Ok( CheatCode {
+        address: (d[ 6 ] << 12) | (d[ 7 ] << 8) | (d[ 4 ] << 4) | d[ 5 ],
+        value: ((d[ 2 ] << 4) | d[ 3 ]) as u8,
+        compare: None
+    })
+}
+
+// `AAAAAAVV`, e.g. `7E0DBE05`, with a 24-bit address.
+pub fn parse_snes_action_replay( code: &str ) -> Result< CheatCode, InvalidCheatCode > {
+    let stripped = strip_separators( code );
+    let d = hex_digits( code, &stripped )?;
+    if d.len() != 8 {
+        return Err( InvalidCheatCode::new( code ) );
+    }
+
+    let code = d.iter().fold( 0, |accumulator, &digit| (accumulator << 4) | digit );
+    Ok( CheatCode {
+        address: code >> 8,
+        value: (code & 0xFF) as u8,
+        compare: None
+    })
+}
+
+// Keeps track of the cheats the frontend has enabled and pokes them
+// into the core's memory every frame; see `CoreInfo::builtin_cheats`.
+#[derive(Clone, Default, Debug)]
+pub struct CheatEngine {
+    cheats: BTreeMap< u32, Vec< CheatCode > >
+}
+
+impl CheatEngine {
+    pub fn new() -> CheatEngine {
+        CheatEngine::default()
+    }
+
+    pub fn reset( &mut self ) {
+        self.cheats.clear();
+    }
+
+    pub fn set( &mut self, index: u32, is_enabled: bool, code: &str ) -> Result< (), InvalidCheatCode > {
+        if is_enabled == false {
+            self.cheats.remove( &index );
+            return Ok(());
+        }
+
+        let codes = parse_raw( code )?;
+        self.cheats.insert( index, codes );
+        Ok(())
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Addresses are offsets into `memory`; anything out of bounds is ignored.
+    pub fn apply( &self, memory: &mut [u8] ) {
+        for code in self.cheats.values().flat_map( |codes| codes.iter() ) {
+            let byte = match memory.get_mut( code.address as usize ) {
+                Some( byte ) => byte,
+                None => continue
+            };
+
+            if let Some( compare ) = code.compare {
+                if *byte != compare {
+                    continue;
+                }
+            }
+
+            *byte = code.value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code( address: u32, value: u8, compare: Option< u8 > ) -> CheatCode {
+        CheatCode {
+            address: address,
+            value: value,
+            compare: compare
+        }
+    }
+
+    #[test]
+    fn nes_game_genie_six_letters() {
+        // Infinite lives in Super Mario Bros.
+        assert_eq!( parse_nes_game_genie( "SXIOPO" ), Ok( code( 0x91D9, 0xAD, None ) ) );
+        assert_eq!( parse_nes_game_genie( "gossip" ), Ok( code( 0xD1DD, 0x14, None ) ) );
+    }
+
+    #[test]
+    fn nes_game_genie_eight_letters() {
+        assert_eq!( parse_nes_game_genie( "ZEXPYGLA" ), Ok( code( 0x94A7, 0x02, Some( 0x03 ) ) ) );
+        assert_eq!( parse_nes_game_genie( "ZEXP-YGLA" ), Ok( code( 0x94A7, 0x02, Some( 0x03 ) ) ) );
+    }
+
+    #[test]
+    fn nes_game_genie_rejects_invalid_codes() {
+        assert!( parse_nes_game_genie( "SXIOP" ).is_err() );
+        assert!( parse_nes_game_genie( "SXIOPOA" ).is_err() );
+        assert!( parse_nes_game_genie( "SXIOPB" ).is_err() );
+    }
+
+    #[test]
+    fn game_boy_game_genie() {
+        assert_eq!( parse_game_boy_game_genie( "00A-17B" ), Ok( code( 0x4A17, 0x00, None ) ) );
+        assert_eq!( parse_game_boy_game_genie( "00A-17B-C49" ), Ok( code( 0x4A17, 0x00, Some( 0xC8 ) ) ) );
+        assert_eq!( parse_game_boy_game_genie( "00a17bc49" ), Ok( code( 0x4A17, 0x00, Some( 0xC8 ) ) ) );
+        assert!( parse_game_boy_game_genie( "00A-17" ).is_err() );
+        assert!( parse_game_boy_game_genie( "00A-17B-C4" ).is_err() );
+        assert!( parse_game_boy_game_genie( "00A-17B-C49-0" ).is_err() );
+        assert!( parse_game_boy_game_genie( "00A-17G" ).is_err() );
+    }
+
+    #[test]
+    fn game_boy_gameshark() {
+        // Walking through walls in Pokemon Red and Blue.
+        assert_eq!( parse_game_boy_gameshark( "010138CD" ), Ok( code( 0xCD38, 0x01, None ) ) );
+        assert_eq!( parse_game_boy_gameshark( "01FF16D0" ), Ok( code( 0xD016, 0xFF, None ) ) );
+        assert!( parse_game_boy_gameshark( "010138C" ).is_err() );
+        assert!( parse_game_boy_gameshark( "010138CD0" ).is_err() );
+        assert!( parse_game_boy_gameshark( "010138CX" ).is_err() );
+    }
+
+    #[test]
+    fn snes_action_replay() {
+        assert_eq!( parse_snes_action_replay( "7E0DBE05" ), Ok( code( 0x7E0DBE, 0x05, None ) ) );
+        assert_eq!( parse_snes_action_replay( "7E0019-FF" ), Ok( code( 0x7E0019, 0xFF, None ) ) );
+        assert!( parse_snes_action_replay( "7E0DBE0" ).is_err() );
+        assert!( parse_snes_action_replay( "7E0DBEXX" ).is_err() );
+    }
+
+    #[test]
+    fn raw_codes() {
+        assert_eq!( parse_raw( "C0A5:63 + C0A6:FF:01" ), Ok( vec![ code( 0xC0A5, 0x63, None ), code( 0xC0A6, 0x01, Some( 0xFF ) ) ] ) );
+        assert!( parse_raw( "C0A5:100" ).is_err() );
+        assert!( parse_raw( "C0A5" ).is_err() );
+    }
+
+    #[test]
+    fn apply_writes_enabled_cheats() {
+        let mut engine = CheatEngine::new();
+        engine.set( 0, true, "1:AA" ).unwrap();
+        engine.set( 1, true, "2:10:BB+3:10:CC" ).unwrap();
+
+        let mut memory = [0x10; 4];
+        engine.apply( &mut memory );
+        assert_eq!( memory, [0x10, 0xAA, 0xBB, 0xCC] );
+    }
+
+    #[test]
+    fn apply_skips_mismatched_compare_values() {
+        let mut engine = CheatEngine::new();
+        engine.set( 0, true, "0:10:AA+1:20:BB" ).unwrap();
+
+        let mut memory = [0x10, 0x10];
+        engine.apply( &mut memory );
+        assert_eq!( memory, [0xAA, 0x10] );
+    }
+
+    #[test]
+    fn apply_ignores_out_of_bounds_addresses() {
+        let mut engine = CheatEngine::new();
+        engine.set( 0, true, "0:AA+100:BB" ).unwrap();
+
+        let mut memory = [0; 2];
+        engine.apply( &mut memory );
+        assert_eq!( memory, [0xAA, 0] );
+    }
+
+    #[test]
+    fn disabled_cheats_are_not_applied() {
+        let mut engine = CheatEngine::new();
+        engine.set( 0, true, "0:AA" ).unwrap();
+        engine.set( 1, true, "1:BB" ).unwrap();
+        engine.set( 0, false, "0:AA" ).unwrap();
+
+        let mut memory = [0; 2];
+        engine.apply( &mut memory );
+        assert_eq!( memory, [0, 0xBB] );
+
+        engine.reset();
+        assert!( engine.is_empty() );
+    }
+}
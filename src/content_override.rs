@@ -0,0 +1,87 @@
+use std::ffi::CString;
+use std::ptr;
+
+use libc;
+
+use environment::call_environment;
+
+const ENVIRONMENT_SET_CONTENT_INFO_OVERRIDE: libc::c_uint = 65;
+
+#[repr(C)]
+struct RawContentInfoOverride {
+    extensions: *const libc::c_char,
+    need_fullpath: bool,
+    persistent_data: bool
+}
+
+// Overrides how content with the given extensions gets loaded;
+// anything not listed here uses the settings from `CoreInfo`.
+pub struct ContentOverride {
+    extensions: CString,
+    needs_full_path: bool,
+    persistent_data: bool
+}
+
+impl ContentOverride {
+    // A `|` separated list, e.g. "iso|chd".
+    pub fn new( extensions: &str ) -> ContentOverride {
+        ContentOverride {
+            extensions: CString::new( extensions ).unwrap(),
+            needs_full_path: false,
+            persistent_data: false
+        }
+    }
+
+    // The frontend won't load the content into memory at all.
+    pub fn needs_full_path( mut self ) -> Self {
+        self.needs_full_path = true;
+        self
+    }
+
+    // The frontend keeps the content in memory until the game is
+    // unloaded, so `GameData::persistent_data` can be used instead
+    // of making a copy.
+    pub fn persistent_data( mut self ) -> Self {
+        self.persistent_data = true;
+        self
+    }
+}
+
+pub(crate) struct ContentOverrides {
+    overrides: Vec< ContentOverride >,
+    raw: Vec< RawContentInfoOverride >
+}
+
+impl ContentOverrides {
+    pub fn new() -> ContentOverrides {
+        ContentOverrides {
+            overrides: Vec::new(),
+            raw: vec![ RawContentInfoOverride {
+                extensions: ptr::null(),
+                need_fullpath: false,
+                persistent_data: false
+            }]
+        }
+    }
+
+    pub fn push( &mut self, content_override: ContentOverride ) {
+        let terminator = self.raw.pop().unwrap();
+        self.raw.push( RawContentInfoOverride {
+            extensions: content_override.extensions.as_ptr(),
+            need_fullpath: content_override.needs_full_path,
+            persistent_data: content_override.persistent_data
+        });
+        self.raw.push( terminator );
+        self.overrides.push( content_override );
+    }
+
+    pub fn register( &self ) {
+        if self.overrides.is_empty() {
+            return;
+        }
+
+        unsafe {
+            let _ = call_environment( ENVIRONMENT_SET_CONTENT_INFO_OVERRIDE, &self.raw[ 0 ] );
+        }
+    }
+}
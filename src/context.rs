@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+use std::ptr;
+
+use libc;
+use libretro_sys;
+
+use environment::{self, call_environment_mut, call_environment_raw, EnvError};
+use input::{ENVIRONMENT_GET_INPUT_BITMASKS, ENVIRONMENT_GET_INPUT_MAX_USERS};
+use language::Language;
+use logging;
+use message::LogLevel;
+#[cfg(feature = "options")]
+use options::{self, FromOptionValue};
+use perf;
+use sensor::Sensors;
+use midi::Midi;
+
+// Everything the core can ask the frontend for outside of a frame, e.g. in
+// `Core::create`; only usable once the frontend has given us its environment
+// callback, which is always the case by the time `retro_init` is called.
+pub struct FrontendContext {
+    _private: ()
+}
+
+impl FrontendContext {
+    #[doc(hidden)]
+    pub fn new() -> FrontendContext {
+        FrontendContext {
+            _private: ()
+        }
+    }
+
+    pub fn log( &self, level: LogLevel, message: &str ) {
+        logging::log( level, message );
+    }
+
+    pub fn system_directory( &self ) -> Result< PathBuf, EnvError > {
+        environment::get_system_directory()
+    }
+
+    pub fn save_directory( &self ) -> Result< PathBuf, EnvError > {
+        environment::get_save_directory()
+    }
+
+    pub fn core_assets_directory( &self ) -> Result< PathBuf, EnvError > {
+        environment::get_core_assets_directory()
+    }
+
+    pub fn libretro_path( &self ) -> Result< PathBuf, EnvError > {
+        environment::get_libretro_path()
+    }
+
+    pub fn username( &self ) -> Result< String, EnvError > {
+        environment::get_username()
+    }
+
+    pub fn language( &self ) -> Result< Language, EnvError > {
+        environment::get_language()
+    }
+
+    // The options from `CoreInfo` are already registered at this point.
+    #[cfg(feature = "options")]
+    pub fn option( &self, key: &str ) -> Option< String > {
+        options::get( key )
+    }
+
+    #[cfg(feature = "options")]
+    pub fn option_as< T: FromOptionValue >( &self, key: &str ) -> Option< T > {
+        options::get_as( key )
+    }
+
+    #[cfg(feature = "options")]
+    pub fn set_option_visible( &self, key: &str, is_visible: bool ) -> Result< (), EnvError > {
+        options::set_visible( key, is_visible )
+    }
+
+    pub fn supports_input_bitmasks( &self ) -> bool {
+        unsafe {
+            call_environment_raw( ENVIRONMENT_GET_INPUT_BITMASKS, ptr::null_mut() ).is_ok()
+        }
+    }
+
+    pub fn can_dupe_video_frame( &self ) -> bool {
+        let mut can_dupe = false;
+        unsafe {
+            call_environment_mut( libretro_sys::ENVIRONMENT_GET_CAN_DUPE, &mut can_dupe ).is_ok() && can_dupe
+        }
+    }
+
+    // `None` if the frontend doesn't say how many players it supports.
+    pub fn max_users( &self ) -> Option< u32 > {
+        let mut max_users: libc::c_uint = 0;
+        let result = unsafe { call_environment_mut( ENVIRONMENT_GET_INPUT_MAX_USERS, &mut max_users ) };
+        match result {
+            Ok(()) if max_users > 0 => Some( max_users as u32 ),
+            _ => None
+        }
+    }
+
+    pub fn sensors( &self ) -> Option< Sensors > {
+        Sensors::query()
+    }
+
+    pub fn midi( &self ) -> Option< Midi > {
+        Midi::query()
+    }
+
+    // A bitmask of the frontend's `RETRO_SIMD_*` flags.
+    pub fn cpu_features( &self ) -> Option< u64 > {
+        perf::cpu_features()
+    }
+}
@@ -0,0 +1,59 @@
+use frame::{Frame, Pixel};
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+
+// A 3x5 font for the characters from ' ' to '_', one bit per pixel
+// starting from the top left; lowercase letters use the uppercase glyphs.
+const FONT: [u16; 64] = [
+    0x0000, 0x2482, 0x5A00, 0x5F7D, 0x3C9E, 0x42A1, 0x2AAB, 0x2400,
+    0x1491, 0x4494, 0x0AA8, 0x05D0, 0x0014, 0x01C0, 0x0002, 0x12A4,
+    0x7B6F, 0x2C97, 0x73E7, 0x72CF, 0x5BC9, 0x79CF, 0x79EF, 0x7292,
+    0x7BEF, 0x7BCF, 0x0410, 0x0414, 0x1511, 0x0E38, 0x4454, 0x72C2,
+    0x7B67, 0x2BED, 0x6BAE, 0x3923, 0x6B6E, 0x79A7, 0x79A4, 0x396B,
+    0x5BED, 0x7497, 0x126A, 0x5BAD, 0x4927, 0x5FED, 0x6B6D, 0x2B6A,
+    0x6BA4, 0x2B73, 0x6BAD, 0x388E, 0x7492, 0x5B6F, 0x5B6A, 0x5BFD,
+    0x5AAD, 0x5A92, 0x72A7, 0x3493, 0x4889, 0x6496, 0x2A00, 0x0007
+];
+
+fn glyph( character: char ) -> u16 {
+    let character = character.to_ascii_uppercase() as u32;
+    if character < 0x20 || character >= 0x20 + FONT.len() as u32 {
+        // Shown as a '?'.
+        return FONT[ ('?' as u32 - 0x20) as usize ];
+    }
+
+    FONT[ (character - 0x20) as usize ]
+}
+
+// Draws the text with its top left corner at the given position; anything
+// outside of the frame is clipped. Returns the size of the drawn text.
+pub fn draw_text< P: Pixel >( frame: &mut Frame< P >, x: u32, y: u32, text: &str, color: P ) -> (u32, u32) {
+    let mut cursor_x = x;
+    let mut cursor_y = y;
+    let mut width = 0;
+    for character in text.chars() {
+        if character == '\n' {
+            cursor_x = x;
+            cursor_y += LINE_HEIGHT;
+            continue;
+        }
+
+        let bits = glyph( character );
+        for row in 0..GLYPH_HEIGHT {
+            for column in 0..GLYPH_WIDTH {
+                let bit = (GLYPH_HEIGHT - row) * GLYPH_WIDTH - column - 1;
+                if bits & (1 << bit) != 0 {
+                    frame.set( cursor_x + column, cursor_y + row, color );
+                }
+            }
+        }
+
+        cursor_x += ADVANCE;
+        width = ::std::cmp::max( width, cursor_x - x );
+    }
+
+    (width, cursor_y - y + GLYPH_HEIGHT)
+}
@@ -0,0 +1,90 @@
+use logging;
+use message::LogLevel;
+use {Core, SavestateContext};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn hash( data: &[u8] ) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul( FNV_PRIME );
+    }
+
+    hash
+}
+
+fn first_difference( lhs: &[u8], rhs: &[u8] ) -> usize {
+    lhs.iter().zip( rhs.iter() ).position( |(lhs, rhs)| lhs != rhs ).unwrap_or( ::std::cmp::min( lhs.len(), rhs.len() ) )
+}
+
+// Checks after every frame that the core's savestates round-trip,
+// which is what rollback netplay and runahead depend on.
+//
+// Only the first divergence is reported since everything
+// after that is usually just fallout from it.
+#[derive(Default)]
+pub(crate) struct DeterminismChecker {
+    frame: u64,
+    first: Vec< u8 >,
+    second: Vec< u8 >,
+    has_diverged: bool
+}
+
+impl DeterminismChecker {
+    fn serialize< B: Core >( core: &B, buffer: &mut Vec< u8 > ) -> bool {
+        let size = core.serialize_size( SavestateContext::Normal );
+        if size == 0 {
+            return false;
+        }
+
+        buffer.clear();
+        buffer.resize( size, 0 );
+        core.serialize( buffer, SavestateContext::Normal ).is_ok()
+    }
+
+    fn report( &mut self, what: &str ) {
+        self.has_diverged = true;
+        let message = if self.first.len() != self.second.len() {
+            format!( "Determinism check failed on frame {}: {}; the savestate size changed from {} to {} bytes", self.frame, what, self.first.len(), self.second.len() )
+        } else {
+            format!(
+                "Determinism check failed on frame {}: {}; first difference at byte {} (hash {:016x} != {:016x})",
+                self.frame,
+                what,
+                first_difference( &self.first, &self.second ),
+                hash( &self.first ),
+                hash( &self.second )
+            )
+        };
+
+        logging::log( LogLevel::Error, &message );
+    }
+
+    pub fn check< B: Core >( &mut self, core: &mut B ) {
+        self.frame += 1;
+        if self.has_diverged {
+            return;
+        }
+
+        if Self::serialize( core, &mut self.first ) == false {
+            return;
+        }
+
+        if Self::serialize( core, &mut self.second ) == false || hash( &self.first ) != hash( &self.second ) {
+            self.report( "serializing twice in a row gave different savestates" );
+            return;
+        }
+
+        if let Err( error ) = core.unserialize( &self.first, SavestateContext::Normal ) {
+            self.has_diverged = true;
+            logging::log( LogLevel::Error, &format!( "Determinism check failed on frame {}: the core refused to load its own savestate: {}", self.frame, error ) );
+            return;
+        }
+
+        if Self::serialize( core, &mut self.second ) == false || hash( &self.first ) != hash( &self.second ) {
+            self.report( "loading a savestate and serializing it again gave a different savestate" );
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::ffi::CStr;
+use std::ptr;
+
+use libc;
+use libretro_sys;
+
+use environment::{call_environment, call_environment_mut};
+use {Core, Retro};
+
+const ENVIRONMENT_GET_DISK_CONTROL_INTERFACE_VERSION: libc::c_uint = 57;
+const ENVIRONMENT_SET_DISK_CONTROL_EXT_INTERFACE: libc::c_uint = 58;
+
+// Implemented by cores which support multi-disc games; the frontend
+// uses this to let the user swap discs at runtime.
+pub trait DiskControl {
+    fn is_ejected( &self ) -> bool;
+    fn set_ejected( &mut self, is_ejected: bool ) -> bool;
+    fn image_index( &self ) -> u32;
+    // The index can be equal to `image_count`, which means "no disc".
+    fn set_image_index( &mut self, index: u32 ) -> bool;
+    fn image_count( &self ) -> u32;
+    // A `None` path removes the image.
+    fn replace_image( &mut self, index: u32, path: Option< &str > ) -> bool;
+    fn add_image( &mut self ) -> bool;
+
+    // Called before the game is loaded with the image that was used last time.
+    fn set_initial_image( &mut self, _index: u32, _path: &str ) -> bool {
+        false
+    }
+
+    fn image_path( &self, _index: u32 ) -> Option< String > {
+        None
+    }
+
+    fn image_label( &self, _index: u32 ) -> Option< String > {
+        None
+    }
+}
+
+#[repr(C)]
+struct RawDiskControlCallback {
+    set_eject_state: unsafe extern "C" fn( ejected: bool ) -> bool,
+    get_eject_state: unsafe extern "C" fn() -> bool,
+    get_image_index: unsafe extern "C" fn() -> libc::c_uint,
+    set_image_index: unsafe extern "C" fn( index: libc::c_uint ) -> bool,
+    get_num_images: unsafe extern "C" fn() -> libc::c_uint,
+    replace_image_index: unsafe extern "C" fn( index: libc::c_uint, info: *const libretro_sys::GameInfo ) -> bool,
+    add_image_index: unsafe extern "C" fn() -> bool
+}
+
+#[repr(C)]
+struct RawDiskControlExtCallback {
+    base: RawDiskControlCallback,
+    set_initial_image: unsafe extern "C" fn( index: libc::c_uint, path: *const libc::c_char ) -> bool,
+    get_image_path: unsafe extern "C" fn( index: libc::c_uint, path: *mut libc::c_char, length: libc::size_t ) -> bool,
+    get_image_label: unsafe extern "C" fn( index: libc::c_uint, label: *mut libc::c_char, length: libc::size_t ) -> bool
+}
+
+unsafe fn with_disk_control< B: Core, R, F: FnOnce( &mut dyn DiskControl ) -> R >( default: R, callback: F ) -> R {
+    let retro = match Retro::< B >::active() {
+        Some( retro ) => retro,
+        None => return default
+    };
+
+    match retro.core.disk_control() {
+        Some( disk_control ) => ::catch_panic( || callback( disk_control ) ).unwrap_or( default ),
+        None => default
+    }
+}
+
+unsafe extern "C" fn set_eject_state< B: Core >( ejected: bool ) -> bool {
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.set_ejected( ejected ) )
+}
+
+unsafe extern "C" fn get_eject_state< B: Core >() -> bool {
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.is_ejected() )
+}
+
+unsafe extern "C" fn get_image_index< B: Core >() -> libc::c_uint {
+    with_disk_control::< B, _, _ >( 0, |disk_control| disk_control.image_index() as libc::c_uint )
+}
+
+unsafe extern "C" fn set_image_index< B: Core >( index: libc::c_uint ) -> bool {
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.set_image_index( index as u32 ) )
+}
+
+unsafe extern "C" fn get_num_images< B: Core >() -> libc::c_uint {
+    with_disk_control::< B, _, _ >( 0, |disk_control| disk_control.image_count() as libc::c_uint )
+}
+
+unsafe extern "C" fn replace_image_index< B: Core >( index: libc::c_uint, info: *const libretro_sys::GameInfo ) -> bool {
+    let path = if info == ptr::null() || (*info).path == ptr::null() {
+        None
+    } else {
+        match CStr::from_ptr( (*info).path ).to_str() {
+            Ok( path ) => Some( path ),
+            Err( _ ) => return false
+        }
+    };
+
+    if info != ptr::null() && path.is_none() {
+        // Loading disc images from memory isn't supported.
+        return false;
+    }
+
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.replace_image( index as u32, path ) )
+}
+
+unsafe extern "C" fn add_image_index< B: Core >() -> bool {
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.add_image() )
+}
+
+unsafe extern "C" fn set_initial_image< B: Core >( index: libc::c_uint, path: *const libc::c_char ) -> bool {
+    if path == ptr::null() {
+        return false;
+    }
+
+    let path = match CStr::from_ptr( path ).to_str() {
+        Ok( path ) => path,
+        Err( _ ) => return false
+    };
+
+    with_disk_control::< B, _, _ >( false, |disk_control| disk_control.set_initial_image( index as u32, path ) )
+}
+
+unsafe fn copy_string( value: Option< String >, output: *mut libc::c_char, length: libc::size_t ) -> bool {
+    let value = match value {
+        Some( value ) => value,
+        None => return false
+    };
+
+    if output == ptr::null_mut() || length == 0 {
+        return false;
+    }
+
+    let count = ::std::cmp::min( value.len(), length - 1 );
+    ptr::copy_nonoverlapping( value.as_ptr() as *const libc::c_char, output, count );
+    *output.offset( count as isize ) = 0;
+    true
+}
+
+unsafe extern "C" fn get_image_path< B: Core >( index: libc::c_uint, path: *mut libc::c_char, length: libc::size_t ) -> bool {
+    let value = with_disk_control::< B, _, _ >( None, |disk_control| disk_control.image_path( index as u32 ) );
+    copy_string( value, path, length )
+}
+
+unsafe extern "C" fn get_image_label< B: Core >( index: libc::c_uint, label: *mut libc::c_char, length: libc::size_t ) -> bool {
+    let value = with_disk_control::< B, _, _ >( None, |disk_control| disk_control.image_label( index as u32 ) );
+    copy_string( value, label, length )
+}
+
+pub(crate) fn register< B: Core >() {
+    let callback = RawDiskControlExtCallback {
+        base: RawDiskControlCallback {
+            set_eject_state: set_eject_state::< B >,
+            get_eject_state: get_eject_state::< B >,
+            get_image_index: get_image_index::< B >,
+            set_image_index: set_image_index::< B >,
+            get_num_images: get_num_images::< B >,
+            replace_image_index: replace_image_index::< B >,
+            add_image_index: add_image_index::< B >
+        },
+        set_initial_image: set_initial_image::< B >,
+        get_image_path: get_image_path::< B >,
+        get_image_label: get_image_label::< B >
+    };
+
+    let mut version: libc::c_uint = 0;
+    unsafe {
+        if call_environment_mut( ENVIRONMENT_GET_DISK_CONTROL_INTERFACE_VERSION, &mut version ).is_ok() && version >= 1 {
+            let _ = call_environment( ENVIRONMENT_SET_DISK_CONTROL_EXT_INTERFACE, &callback );
+        } else {
+            let _ = call_environment( libretro_sys::ENVIRONMENT_SET_DISK_CONTROL_INTERFACE, &callback.base );
+        }
+    }
+}
@@ -0,0 +1,368 @@
+#[cfg(not(feature = "minimal"))]
+use std::env;
+use std::marker::PhantomData;
+use std::path::Path;
+
+#[cfg(feature = "glow")]
+use glow;
+
+use {
+    AudioVideoInfo,
+    Core,
+    CoreInfo,
+    DiskControl,
+    FrontendContext,
+    GameData,
+    Key,
+    KeyModifiers,
+    LoadGameError,
+    Netpacket,
+    RuntimeHandle,
+    SavestateContext
+};
+
+// Every method which `DynamicCore` and `MultiCore` pass straight through
+// to the core underneath, along with what `MultiCore` does when it doesn't
+// have one. `fallible` methods have their errors turned into strings.
+macro_rules! core_methods {
+    ($mode: ident) => {
+        core_methods!( @each $mode
+            (mut plain) fn on_run( handle: &mut RuntimeHandle ) -> () = ();
+            (mut plain) fn on_reset() -> () = ();
+            (mut plain) fn save_memory() -> Option< &mut [u8] > = None;
+            (mut plain) fn rtc_memory() -> Option< &mut [u8] > = None;
+            (mut plain) fn system_memory() -> Option< &mut [u8] > = None;
+            (mut plain) fn video_memory() -> Option< &mut [u8] > = None;
+            (ref plain) fn serialize_size( context: SavestateContext ) -> usize = 0;
+            (ref fallible) fn serialize( buffer: &mut [u8], context: SavestateContext ) -> Result< (), String > = Err( NO_CORE_LOADED.to_owned() );
+            (mut fallible) fn unserialize( buffer: &[u8], context: SavestateContext ) -> Result< (), String > = Err( NO_CORE_LOADED.to_owned() );
+            (mut plain) fn on_keyboard_event( is_pressed: bool, key: Option< Key >, character: Option< char >, modifiers: KeyModifiers ) -> () = ();
+            (mut plain) fn on_set_controller_port_device( port: u32, device: u32 ) -> () = ();
+            (mut plain) fn on_hw_context_reset() -> () = ();
+            (mut plain) fn on_hw_context_destroyed() -> () = ();
+            #[cfg(feature = "glow")]
+            (mut plain) fn on_glow_context_reset( context: glow::Context ) -> () = ();
+            (mut plain) fn disk_control() -> Option< &mut dyn DiskControl > = None;
+            (mut plain) fn on_options_changed( changed_keys: &[&str] ) -> () = ();
+            (mut plain) fn on_update_option_visibility() -> bool = false;
+            (mut plain) fn on_cheat_reset() -> () = ();
+            (mut fallible) fn on_cheat_set( index: u32, is_enabled: bool, code: &str ) -> Result< (), String > = Ok(());
+            (mut plain) fn on_netpacket_start( netpacket: Netpacket ) -> () = ();
+            (mut plain) fn on_netpacket_receive( data: &[u8], client_id: u16 ) -> () = ();
+            (mut plain) fn on_netpacket_stop() -> () = ();
+            (mut plain) fn on_netpacket_poll() -> () = ();
+            (mut plain) fn on_netpacket_connected( client_id: u16 ) -> bool = true;
+            (mut plain) fn on_netpacket_disconnected( client_id: u16 ) -> () = ();
+        );
+    };
+
+    (@each $mode: ident $($(#[$attribute: meta])* ($receiver: tt $kind: ident) fn $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr;)+) => {
+        $(
+            $(#[$attribute])*
+            core_methods!( @method $mode $receiver $kind $name( $($argument: $argument_type),* ) -> $output = $default );
+        )+
+    };
+
+    // The declarations in `CoreDyn`.
+    (@method declare mut $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &mut self $(, $argument: $argument_type)* ) -> $output;
+    };
+    (@method declare ref $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &self $(, $argument: $argument_type)* ) -> $output;
+    };
+
+    // Every `Core` is a `CoreDyn`.
+    (@method core mut plain $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &mut self $(, $argument: $argument_type)* ) -> $output {
+            Core::$name( self $(, $argument)* )
+        }
+    };
+    (@method core ref plain $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &self $(, $argument: $argument_type)* ) -> $output {
+            Core::$name( self $(, $argument)* )
+        }
+    };
+    (@method core mut fallible $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &mut self $(, $argument: $argument_type)* ) -> $output {
+            Core::$name( self $(, $argument)* ).map_err( |error| error.to_string() )
+        }
+    };
+    (@method core ref fallible $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &self $(, $argument: $argument_type)* ) -> $output {
+            Core::$name( self $(, $argument)* ).map_err( |error| error.to_string() )
+        }
+    };
+
+    // `DynamicCore`, which always has a core.
+    (@method boxed mut $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &mut self $(, $argument: $argument_type)* ) -> $output {
+            self.core.$name( $($argument),* )
+        }
+    };
+    (@method boxed ref $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &self $(, $argument: $argument_type)* ) -> $output {
+            self.core.$name( $($argument),* )
+        }
+    };
+
+    // `MultiCore`, which only has a core once a game is loaded.
+    (@method optional mut $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &mut self $(, $argument: $argument_type)* ) -> $output {
+            match self.core {
+                Some( ref mut core ) => core.$name( $($argument),* ),
+                None => $default
+            }
+        }
+    };
+    (@method optional ref $kind: ident $name: ident( $($argument: ident: $argument_type: ty),* ) -> $output: ty = $default: expr) => {
+        fn $name( &self $(, $argument: $argument_type)* ) -> $output {
+            match self.core {
+                Some( ref core ) => core.$name( $($argument),* ),
+                None => $default
+            }
+        }
+    };
+}
+
+const NO_CORE_LOADED: &'static str = "no core is loaded";
+
+// An object-safe mirror of `Core`; every `Core` implements this, so
+// a `Box< dyn CoreDyn >` can hold any of them. The errors are turned
+// into strings since every core can have its own error type.
+pub trait CoreDyn {
+    fn on_load_game( &mut self, game_data: GameData ) -> Result< AudioVideoInfo, LoadGameError< String > >;
+    fn on_unload_game( &mut self ) -> GameData;
+    fn on_load_game_special( &mut self, subsystem_id: u32, games: Vec< GameData > ) -> Result< AudioVideoInfo, LoadGameError< String > >;
+    core_methods!( declare );
+}
+
+impl< T: Core > CoreDyn for T {
+    fn on_load_game( &mut self, game_data: GameData ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        Core::on_load_game( self, game_data ).map_err( |error| error.map_error( |error| error.to_string() ) )
+    }
+
+    fn on_unload_game( &mut self ) -> GameData {
+        Core::on_unload_game( self )
+    }
+
+    fn on_load_game_special( &mut self, subsystem_id: u32, games: Vec< GameData > ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        Core::on_load_game_special( self, subsystem_id, games ).map_err( |error| error.map_error( |error| error.to_string() ) )
+    }
+
+    core_methods!( core );
+}
+
+// Supplies the parts of `Core` which can't go through a trait object.
+pub trait CoreFactory: 'static {
+    fn info() -> CoreInfo;
+    fn create() -> Box< dyn CoreDyn >;
+}
+
+// Lets a core picked at runtime be used wherever a `Core` is expected,
+// e.g. `libretro_core!( DynamicCore< MyFactory > )`.
+pub struct DynamicCore< F: CoreFactory > {
+    core: Box< dyn CoreDyn >,
+    phantom: PhantomData< F >
+}
+
+impl< F: CoreFactory > DynamicCore< F > {
+    pub fn new( core: Box< dyn CoreDyn > ) -> Self {
+        DynamicCore {
+            core: core,
+            phantom: PhantomData
+        }
+    }
+
+    pub fn inner( &mut self ) -> &mut dyn CoreDyn {
+        &mut *self.core
+    }
+}
+
+impl< F: CoreFactory > Default for DynamicCore< F > {
+    fn default() -> Self {
+        DynamicCore::new( F::create() )
+    }
+}
+
+impl< F: CoreFactory > Core for DynamicCore< F > {
+    type Error = String;
+
+    fn info() -> CoreInfo {
+        F::info()
+    }
+
+    fn on_load_game( &mut self, game_data: GameData ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        self.core.on_load_game( game_data )
+    }
+
+    fn on_unload_game( &mut self ) -> GameData {
+        self.core.on_unload_game()
+    }
+
+    fn on_load_game_special( &mut self, subsystem_id: u32, games: Vec< GameData > ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        self.core.on_load_game_special( subsystem_id, games )
+    }
+
+    core_methods!( boxed );
+}
+
+// One of the cores which `MultiCore` can pick from.
+pub struct CoreChoice {
+    name: String,
+    extensions: Vec< String >,
+    create: Box< dyn Fn() -> Box< dyn CoreDyn > >
+}
+
+impl CoreChoice {
+    // Picked for the extensions `C::info()` says it supports; just like with
+    // `libretro_core!` the constructor is only used if `Core::create` returns
+    // `None`, e.g. `CoreChoice::of( Emulator::default )`.
+    pub fn of< C: Core, F: Fn() -> C + 'static >( constructor: F ) -> CoreChoice {
+        let info = C::info();
+        CoreChoice {
+            name: info.library_name.to_string_lossy().into_owned(),
+            extensions: info.supported_romfile_extensions.to_string_lossy()
+                .split( '|' )
+                .filter( |extension| extension.is_empty() == false )
+                .map( |extension| extension.to_lowercase() )
+                .collect(),
+            create: Box::new( move || Box::new( C::create( &FrontendContext::new() ).unwrap_or_else( &constructor ) ) )
+        }
+    }
+
+    pub fn name( &self ) -> &str {
+        &self.name
+    }
+
+    fn supports( &self, extension: &str ) -> bool {
+        self.extensions.iter().any( |supported| supported == extension )
+    }
+}
+
+// Describes a family of cores shipped in a single library.
+pub trait CoreRegistry: 'static {
+    // What gets reported to the frontend, so it should list the
+    // extensions of every core; the cores' own infos are only
+    // used to decide which one gets to load the game.
+    fn info() -> CoreInfo;
+    fn cores() -> Vec< CoreChoice >;
+
+    // When set this environment variable can contain the name
+    // of the core to use, regardless of the game's extension;
+    // ignored with the `minimal` feature.
+    fn environment_variable() -> Option< &'static str > {
+        Some( "LIBRETRO_CORE" )
+    }
+}
+
+fn game_extension( game_data: &GameData ) -> Option< String > {
+    if let Some( extension ) = game_data.extension() {
+        return Some( extension.to_lowercase() );
+    }
+
+    let path = game_data.archive_file().or( game_data.path() )?;
+    Path::new( path ).extension().map( |extension| extension.to_string_lossy().to_lowercase() )
+}
+
+// Picks one of `R::cores()` when a game is loaded, e.g.
+// `libretro_core!( MultiCore< MyRegistry > )`.
+pub struct MultiCore< R: CoreRegistry > {
+    core: Option< Box< dyn CoreDyn > >,
+    phantom: PhantomData< R >
+}
+
+impl< R: CoreRegistry > MultiCore< R > {
+    // The core which has loaded the current game, if any.
+    pub fn inner( &mut self ) -> Option< &mut dyn CoreDyn > {
+        match self.core {
+            Some( ref mut core ) => Some( &mut **core ),
+            None => None
+        }
+    }
+
+    #[cfg(not(feature = "minimal"))]
+    fn forced_core() -> Option< String > {
+        R::environment_variable().and_then( |name| env::var( name ).ok() )
+    }
+
+    #[cfg(feature = "minimal")]
+    fn forced_core() -> Option< String > {
+        None
+    }
+
+    fn select( game_data: &GameData ) -> Option< Box< dyn CoreDyn > > {
+        let cores = R::cores();
+        let forced = Self::forced_core();
+        let choice = match forced {
+            Some( name ) => cores.iter().find( |choice| choice.name == name ),
+            None => {
+                let extension = game_extension( game_data );
+                match extension {
+                    Some( extension ) => cores.iter().find( |choice| choice.supports( &extension ) ),
+                    None => None
+                }.or( cores.first() )
+            }
+        };
+
+        choice.map( |choice| (choice.create)() )
+    }
+
+    fn finish_loading( &mut self, core: Box< dyn CoreDyn >, result: Result< AudioVideoInfo, LoadGameError< String > > ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        if result.is_ok() {
+            self.core = Some( core );
+        }
+
+        result
+    }
+}
+
+impl< R: CoreRegistry > Default for MultiCore< R > {
+    fn default() -> Self {
+        MultiCore {
+            core: None,
+            phantom: PhantomData
+        }
+    }
+}
+
+impl< R: CoreRegistry > Core for MultiCore< R > {
+    type Error = String;
+
+    fn info() -> CoreInfo {
+        R::info()
+    }
+
+    fn on_load_game( &mut self, game_data: GameData ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        let mut core = match Self::select( &game_data ) {
+            Some( core ) => core,
+            None => return Err( LoadGameError::unsupported( game_data ) )
+        };
+
+        let result = core.on_load_game( game_data );
+        self.finish_loading( core, result )
+    }
+
+    fn on_unload_game( &mut self ) -> GameData {
+        match self.core.take() {
+            Some( mut core ) => core.on_unload_game(),
+            None => GameData::empty()
+        }
+    }
+
+    fn on_load_game_special( &mut self, subsystem_id: u32, games: Vec< GameData > ) -> Result< AudioVideoInfo, LoadGameError< String > > {
+        let core = match games.first() {
+            Some( game_data ) => Self::select( game_data ),
+            None => Self::select( &GameData::empty() )
+        };
+
+        let mut core = match core {
+            Some( core ) => core,
+            None => return Err( LoadGameError::unsupported( games.into_iter().next().unwrap_or_else( GameData::empty ) ) )
+        };
+
+        let result = core.on_load_game_special( subsystem_id, games );
+        self.finish_loading( core, result )
+    }
+
+    core_methods!( optional );
+}
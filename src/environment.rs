@@ -0,0 +1,294 @@
+use std::cell::Cell;
+use std::cmp::max;
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+#[cfg(feature = "system-clock")]
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+#[cfg(feature = "system-clock")]
+use std::time::Instant;
+
+use libc;
+use libretro_sys;
+
+use language::Language;
+use perf;
+
+// Stored as an address so that it can live in a plain `static`; zero means it's not set.
+static ENVIRONMENT_CALLBACK: AtomicUsize = AtomicUsize::new( 0 );
+
+pub(crate) fn set_environment_callback( callback: Option< libretro_sys::EnvironmentFn > ) {
+    ENVIRONMENT_CALLBACK.store( callback.map( |callback| callback as usize ).unwrap_or( 0 ), Ordering::Release );
+}
+
+thread_local! {
+    // Set while inside of `Retro::enter`, and takes priority over the global one.
+    static CURRENT_ENVIRONMENT_CALLBACK: Cell< Option< libretro_sys::EnvironmentFn > > = Cell::new( None );
+}
+
+pub(crate) fn replace_current_environment_callback( callback: Option< libretro_sys::EnvironmentFn > ) -> Option< libretro_sys::EnvironmentFn > {
+    CURRENT_ENVIRONMENT_CALLBACK.with( |current| current.replace( callback ) )
+}
+
+pub(crate) fn environment_callback() -> Option< libretro_sys::EnvironmentFn > {
+    if let Some( callback ) = CURRENT_ENVIRONMENT_CALLBACK.with( |current| current.get() ) {
+        return Some( callback );
+    }
+
+    match ENVIRONMENT_CALLBACK.load( Ordering::Acquire ) {
+        0 => None,
+        address => Some( unsafe { mem::transmute::< usize, libretro_sys::EnvironmentFn >( address ) } )
+    }
+}
+
+// The frontend only ever answers with `true` or `false`, so a plain failure
+// can't tell whether it doesn't know about a command or just didn't want to
+// do it; that's reported as `Unsupported`, unless we already know that the
+// frontend supports the command (e.g. because it told us the version of
+// the interface it belongs to), in which case it's `Refused`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EnvError {
+    // The frontend hasn't given us its environment callback yet.
+    CallbackNotSet,
+    // The frontend failed a command it might not know about.
+    Unsupported,
+    // The frontend failed a command it's known to support, or
+    // accepted it but didn't give us anything usable.
+    Refused,
+    // The command wasn't sent since the core asked for something that's
+    // not allowed, e.g. a geometry bigger than the maximum video size.
+    InvalidArgument
+}
+
+impl fmt::Display for EnvError {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        let message = match *self {
+            EnvError::CallbackNotSet => "the environment callback is not set",
+            EnvError::Unsupported => "the frontend doesn't support this environment command",
+            EnvError::Refused => "the frontend refused the environment command",
+            EnvError::InvalidArgument => "the arguments of the environment command are invalid"
+        };
+
+        formatter.write_str( message )
+    }
+}
+
+impl error::Error for EnvError {}
+
+#[must_use]
+pub(crate) unsafe fn call_environment< T >( command: libc::c_uint, pointer: &T ) -> Result< (), EnvError > {
+    call_environment_raw( command, pointer as *const T as *mut libc::c_void )
+}
+
+#[must_use]
+pub(crate) unsafe fn call_environment_mut< T >( command: libc::c_uint, pointer: &mut T ) -> Result< (), EnvError > {
+    call_environment_raw( command, pointer as *mut T as *mut libc::c_void )
+}
+
+#[must_use]
+pub(crate) unsafe fn call_environment_raw( command: libc::c_uint, pointer: *mut libc::c_void ) -> Result< (), EnvError > {
+    let callback = match environment_callback() {
+        Some( callback ) => callback,
+        None => return Err( EnvError::CallbackNotSet )
+    };
+
+    let ok = callback( command, pointer );
+    if ok {
+        Ok(())
+    } else {
+        Err( EnvError::Unsupported )
+    }
+}
+
+// For commands which the frontend is already known to support.
+pub(crate) fn known_supported( result: Result< (), EnvError > ) -> Result< (), EnvError > {
+    match result {
+        Err( EnvError::Unsupported ) => Err( EnvError::Refused ),
+        result => result
+    }
+}
+
+fn get_string( command: libc::c_uint ) -> Result< String, EnvError > {
+    let mut pointer: *const libc::c_char = ptr::null();
+    unsafe {
+        call_environment_mut( command, &mut pointer )?;
+        if pointer == ptr::null() {
+            return Err( EnvError::Refused );
+        }
+
+        CStr::from_ptr( pointer ).to_str().map( |value| value.to_owned() ).map_err( |_| EnvError::Refused )
+    }
+}
+
+pub fn get_system_directory() -> Result< PathBuf, EnvError > {
+    get_string( libretro_sys::ENVIRONMENT_GET_SYSTEM_DIRECTORY ).map( PathBuf::from )
+}
+
+pub fn get_save_directory() -> Result< PathBuf, EnvError > {
+    get_string( libretro_sys::ENVIRONMENT_GET_SAVE_DIRECTORY ).map( PathBuf::from )
+}
+
+pub fn get_core_assets_directory() -> Result< PathBuf, EnvError > {
+    get_string( libretro_sys::ENVIRONMENT_GET_CORE_ASSETS_DIRECTORY ).map( PathBuf::from )
+}
+
+// The path of the core's own dynamic library.
+pub fn get_libretro_path() -> Result< PathBuf, EnvError > {
+    get_string( libretro_sys::ENVIRONMENT_GET_LIBRETRO_PATH ).map( PathBuf::from )
+}
+
+pub fn get_username() -> Result< String, EnvError > {
+    get_string( libretro_sys::ENVIRONMENT_GET_USERNAME )
+}
+
+// Languages newer than this crate are reported as English.
+pub fn get_language() -> Result< Language, EnvError > {
+    let mut language: libc::c_uint = 0;
+    unsafe {
+        call_environment_mut( libretro_sys::ENVIRONMENT_GET_LANGUAGE, &mut language )?;
+    }
+
+    Ok( Language::from_raw( language as u32 ).unwrap_or( Language::English ) )
+}
+
+// A point in time as seen by the frontend's clock, which is what the rest
+// of the frontend (e.g. its frame pacing) is going to be consistent with.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct FrontendInstant {
+    usec: i64
+}
+
+impl FrontendInstant {
+    pub fn now() -> FrontendInstant {
+        frontend_time()
+    }
+
+    pub fn as_usec( &self ) -> i64 {
+        self.usec
+    }
+
+    pub fn duration_since( &self, earlier: FrontendInstant ) -> Duration {
+        Duration::from_micros( max( self.usec - earlier.usec, 0 ) as u64 )
+    }
+
+    pub fn elapsed( &self ) -> Duration {
+        frontend_time().duration_since( *self )
+    }
+}
+
+struct Clock {
+    last_usec: i64,
+    // Only used without the `system-clock` feature.
+    emulated_usec: i64
+}
+
+// A mutex instead of atomics since some targets (e.g. 32-bit PowerPC) don't have 64-bit ones.
+static CLOCK: Mutex< Clock > = Mutex::new( Clock {
+    last_usec: i64::MIN,
+    emulated_usec: 0
+});
+
+#[cfg(feature = "system-clock")]
+fn fallback_time_usec( _clock: &Clock ) -> i64 {
+    static START: OnceLock< Instant > = OnceLock::new();
+    START.get_or_init( Instant::now ).elapsed().as_micros() as i64
+}
+
+// Some platforms don't have a precise clock at all, so
+// the time just advances by one frame every frame instead.
+#[cfg(not(feature = "system-clock"))]
+fn fallback_time_usec( clock: &Clock ) -> i64 {
+    clock.emulated_usec
+}
+
+pub(crate) fn advance_emulated_clock( frames_per_second: f64 ) {
+    if cfg!( feature = "system-clock" ) || frames_per_second <= 0.0 {
+        return;
+    }
+
+    if let Ok( mut clock ) = CLOCK.lock() {
+        clock.emulated_usec += (1_000_000.0 / frames_per_second) as i64;
+    }
+}
+
+// Falls back to the system's clock if the frontend doesn't have one.
+pub fn frontend_time() -> FrontendInstant {
+    let mut clock = CLOCK.lock().unwrap_or_else( |error| error.into_inner() );
+    let usec = match perf::time_usec() {
+        Some( usec ) => usec,
+        None => fallback_time_usec( &clock )
+    };
+
+    clock.last_usec = max( clock.last_usec, usec );
+    FrontendInstant {
+        usec: clock.last_usec
+    }
+}
+
+const ENVIRONMENT_SET_FASTFORWARDING_OVERRIDE: libc::c_uint = 64;
+const ENVIRONMENT_GET_SAVESTATE_CONTEXT: libc::c_uint = 72 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+
+// Why the frontend wants a savestate; the states made for runahead
+// never leave the process, so they can skip anything that's only
+// needed to restore a state from disk.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum SavestateContext {
+    Normal,
+    RunaheadSameInstance,
+    RunaheadSameBinary,
+    RollbackNetplay
+}
+
+impl SavestateContext {
+    pub(crate) fn query() -> SavestateContext {
+        let mut context: libc::c_int = 0;
+        unsafe {
+            if call_environment_mut( ENVIRONMENT_GET_SAVESTATE_CONTEXT, &mut context ).is_err() {
+                return SavestateContext::Normal;
+            }
+        }
+
+        match context {
+            1 => SavestateContext::RunaheadSameInstance,
+            2 => SavestateContext::RunaheadSameBinary,
+            3 => SavestateContext::RollbackNetplay,
+            _ => SavestateContext::Normal
+        }
+    }
+}
+
+// A `ratio` of 0.0 leaves the speed up to the frontend.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FastForwarding {
+    pub ratio: f32,
+    pub is_enabled: bool,
+    pub show_notification: bool,
+    pub inhibit_toggle: bool
+}
+
+#[repr(C)]
+struct RawFastForwardingOverride {
+    ratio: f32,
+    fastforward: bool,
+    notification: bool,
+    inhibit_toggle: bool
+}
+
+pub(crate) fn set_fast_forwarding( fast_forwarding: FastForwarding ) -> Result< (), EnvError > {
+    let value = RawFastForwardingOverride {
+        ratio: fast_forwarding.ratio,
+        fastforward: fast_forwarding.is_enabled,
+        notification: fast_forwarding.show_notification,
+        inhibit_toggle: fast_forwarding.inhibit_toggle
+    };
+
+    unsafe {
+        call_environment( ENVIRONMENT_SET_FASTFORWARDING_OVERRIDE, &value )
+    }
+}
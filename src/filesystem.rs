@@ -0,0 +1,152 @@
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+#[cfg(feature = "vfs")]
+use vfs::{self, Vfs, VfsFile};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum OpenMode {
+    Read,
+    // Creates the file, truncating it if it already exists.
+    Write,
+    ReadWrite,
+    // Opens an existing file for writing without truncating it.
+    Update
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Metadata {
+    // The frontend's virtual file system only reports sizes which fit into 32 bits.
+    pub size: u64,
+    pub is_directory: bool,
+    pub is_character_special: bool
+}
+
+pub trait File: Read + Write + Seek {
+    fn size( &self ) -> io::Result< u64 >;
+    fn set_len( &mut self, length: u64 ) -> io::Result< () >;
+}
+
+// File access which works the same whether it goes through the
+// frontend's virtual file system or straight to `std::fs`.
+pub trait FileSystem {
+    fn open( &self, path: &Path, mode: OpenMode ) -> io::Result< Box< dyn File > >;
+    fn metadata( &self, path: &Path ) -> io::Result< Metadata >;
+    fn create_dir( &self, path: &Path ) -> io::Result< () >;
+    fn remove( &self, path: &Path ) -> io::Result< () >;
+    fn rename( &self, old_path: &Path, new_path: &Path ) -> io::Result< () >;
+}
+
+#[cfg(feature = "vfs")]
+impl File for VfsFile {
+    fn size( &self ) -> io::Result< u64 > {
+        VfsFile::size( self )
+    }
+
+    fn set_len( &mut self, length: u64 ) -> io::Result< () > {
+        VfsFile::set_len( self, length )
+    }
+}
+
+impl File for fs::File {
+    fn size( &self ) -> io::Result< u64 > {
+        self.metadata().map( |metadata| metadata.len() )
+    }
+
+    fn set_len( &mut self, length: u64 ) -> io::Result< () > {
+        fs::File::set_len( self, length )
+    }
+}
+
+#[cfg(feature = "vfs")]
+pub struct VfsFileSystem {
+    vfs: Vfs
+}
+
+#[cfg(feature = "vfs")]
+impl VfsFileSystem {
+    pub fn new( vfs: Vfs ) -> VfsFileSystem {
+        VfsFileSystem {
+            vfs: vfs
+        }
+    }
+}
+
+#[cfg(feature = "vfs")]
+impl FileSystem for VfsFileSystem {
+    fn open( &self, path: &Path, mode: OpenMode ) -> io::Result< Box< dyn File > > {
+        Ok( Box::new( self.vfs.open( path, mode )? ) )
+    }
+
+    fn metadata( &self, path: &Path ) -> io::Result< Metadata > {
+        self.vfs.stat( path )
+    }
+
+    fn create_dir( &self, path: &Path ) -> io::Result< () > {
+        self.vfs.mkdir( path )
+    }
+
+    fn remove( &self, path: &Path ) -> io::Result< () > {
+        self.vfs.remove( path )
+    }
+
+    fn rename( &self, old_path: &Path, new_path: &Path ) -> io::Result< () > {
+        self.vfs.rename( old_path, new_path )
+    }
+}
+
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn open( &self, path: &Path, mode: OpenMode ) -> io::Result< Box< dyn File > > {
+        let mut options = fs::OpenOptions::new();
+        match mode {
+            OpenMode::Read => options.read( true ),
+            OpenMode::Write => options.write( true ).create( true ).truncate( true ),
+            OpenMode::ReadWrite => options.read( true ).write( true ).create( true ).truncate( true ),
+            OpenMode::Update => options.read( true ).write( true )
+        };
+
+        Ok( Box::new( options.open( path )? ) )
+    }
+
+    fn metadata( &self, path: &Path ) -> io::Result< Metadata > {
+        let metadata = fs::metadata( path )?;
+        Ok( Metadata {
+            size: metadata.len(),
+            is_directory: metadata.is_dir(),
+            is_character_special: false
+        })
+    }
+
+    fn create_dir( &self, path: &Path ) -> io::Result< () > {
+        fs::create_dir( path )
+    }
+
+    fn remove( &self, path: &Path ) -> io::Result< () > {
+        if fs::metadata( path )?.is_dir() {
+            fs::remove_dir( path )
+        } else {
+            fs::remove_file( path )
+        }
+    }
+
+    fn rename( &self, old_path: &Path, new_path: &Path ) -> io::Result< () > {
+        fs::rename( old_path, new_path )
+    }
+}
+
+// Picks the frontend's virtual file system when it has one.
+#[cfg(feature = "vfs")]
+pub fn get() -> Box< dyn FileSystem > {
+    match vfs::get() {
+        Some( vfs ) => Box::new( VfsFileSystem::new( vfs ) ),
+        None => Box::new( StdFileSystem )
+    }
+}
+
+#[cfg(not(feature = "vfs"))]
+pub fn get() -> Box< dyn FileSystem > {
+    Box::new( StdFileSystem )
+}
@@ -0,0 +1,591 @@
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+use libretro_sys::PixelFormat;
+
+use {AudioVideoInfo, RuntimeHandle, UploadError};
+
+pub trait Pixel: Copy + Default {
+    const FORMAT: PixelFormat;
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rgb565( pub u16 );
+
+impl Rgb565 {
+    pub fn new( r: u8, g: u8, b: u8 ) -> Rgb565 {
+        Rgb565( ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3) )
+    }
+}
+
+impl Pixel for Rgb565 {
+    const FORMAT: PixelFormat = PixelFormat::RGB565;
+}
+
+// The alpha bit is ignored by the frontend.
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Argb1555( pub u16 );
+
+impl Argb1555 {
+    pub fn new( r: u8, g: u8, b: u8 ) -> Argb1555 {
+        Argb1555( ((r as u16 >> 3) << 10) | ((g as u16 >> 3) << 5) | (b as u16 >> 3) )
+    }
+}
+
+impl Pixel for Argb1555 {
+    const FORMAT: PixelFormat = PixelFormat::ARGB1555;
+}
+
+// The alpha byte is ignored by the frontend.
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Argb8888( pub u32 );
+
+impl Argb8888 {
+    pub fn new( r: u8, g: u8, b: u8 ) -> Argb8888 {
+        Argb8888( ((r as u32) << 16) | ((g as u32) << 8) | (b as u32) )
+    }
+}
+
+impl Pixel for Argb8888 {
+    const FORMAT: PixelFormat = PixelFormat::ARGB8888;
+}
+
+// A software frame buffer which can be uploaded with `RuntimeHandle::upload_frame`;
+// the pitch is in pixels and can be bigger than the width.
+#[derive(Clone, Debug)]
+pub struct Frame< P: Pixel > {
+    width: u32,
+    height: u32,
+    pitch: u32,
+    pixels: Vec< P >
+}
+
+impl< P: Pixel > Frame< P > {
+    pub fn new( width: u32, height: u32 ) -> Frame< P > {
+        Frame::with_pitch( width, height, width )
+    }
+
+    pub fn with_pitch( width: u32, height: u32, pitch: u32 ) -> Frame< P > {
+        assert!( pitch >= width, "The pitch can't be smaller than the width!" );
+        Frame {
+            width: width,
+            height: height,
+            pitch: pitch,
+            pixels: vec![ P::default(); pitch as usize * height as usize ]
+        }
+    }
+
+    pub fn width( &self ) -> u32 {
+        self.width
+    }
+
+    pub fn height( &self ) -> u32 {
+        self.height
+    }
+
+    pub fn pitch( &self ) -> u32 {
+        self.pitch
+    }
+
+    pub fn get( &self, x: u32, y: u32 ) -> Option< P > {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some( self.pixels[ (y * self.pitch + x) as usize ] )
+    }
+
+    pub fn set( &mut self, x: u32, y: u32, pixel: P ) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+
+        self.pixels[ (y * self.pitch + x) as usize ] = pixel;
+        true
+    }
+
+    pub fn row( &self, y: u32 ) -> &[P] {
+        let offset = (y * self.pitch) as usize;
+        &self.pixels[ offset..offset + self.width as usize ]
+    }
+
+    pub fn row_mut( &mut self, y: u32 ) -> &mut [P] {
+        let offset = (y * self.pitch) as usize;
+        &mut self.pixels[ offset..offset + self.width as usize ]
+    }
+
+    pub fn fill( &mut self, pixel: P ) {
+        for value in self.pixels.iter_mut() {
+            *value = pixel;
+        }
+    }
+
+    // Includes the padding at the end of every row.
+    pub fn pixels( &self ) -> &[P] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut( &mut self ) -> &mut [P] {
+        &mut self.pixels
+    }
+
+    pub fn as_bytes( &self ) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts( self.pixels.as_ptr() as *const u8, self.pixels.len() * mem::size_of::< P >() )
+        }
+    }
+}
+
+impl< P: Pixel > Index< (u32, u32) > for Frame< P > {
+    type Output = P;
+    fn index( &self, (x, y): (u32, u32) ) -> &P {
+        assert!( x < self.width && y < self.height, "Pixel out of bounds!" );
+        &self.pixels[ (y * self.pitch + x) as usize ]
+    }
+}
+
+impl< P: Pixel > IndexMut< (u32, u32) > for Frame< P > {
+    fn index_mut( &mut self, (x, y): (u32, u32) ) -> &mut P {
+        assert!( x < self.width && y < self.height, "Pixel out of bounds!" );
+        &mut self.pixels[ (y * self.pitch + x) as usize ]
+    }
+}
+
+// Anything that pixels can be drawn into; the pitch is in pixels.
+pub trait Surface< P: Pixel > {
+    fn width( &self ) -> u32;
+    fn height( &self ) -> u32;
+    fn pitch( &self ) -> u32;
+    fn pixels( &self ) -> &[P];
+    fn pixels_mut( &mut self ) -> &mut [P];
+}
+
+impl< P: Pixel > Surface< P > for Frame< P > {
+    fn width( &self ) -> u32 {
+        Frame::width( self )
+    }
+
+    fn height( &self ) -> u32 {
+        Frame::height( self )
+    }
+
+    fn pitch( &self ) -> u32 {
+        Frame::pitch( self )
+    }
+
+    fn pixels( &self ) -> &[P] {
+        Frame::pixels( self )
+    }
+
+    fn pixels_mut( &mut self ) -> &mut [P] {
+        Frame::pixels_mut( self )
+    }
+}
+
+// A typed view of memory we don't own, e.g. the frontend's frame buffer.
+pub struct SurfaceMut< 'a, P: Pixel + 'a > {
+    pixels: &'a mut [P],
+    width: u32,
+    height: u32,
+    pitch: u32
+}
+
+impl< 'a, P: Pixel > SurfaceMut< 'a, P > {
+    pub(crate) fn new( pixels: &'a mut [P], width: u32, height: u32, pitch: u32 ) -> SurfaceMut< 'a, P > {
+        SurfaceMut {
+            pixels: pixels,
+            width: width,
+            height: height,
+            pitch: pitch
+        }
+    }
+}
+
+impl< 'a, P: Pixel > Surface< P > for SurfaceMut< 'a, P > {
+    fn width( &self ) -> u32 {
+        self.width
+    }
+
+    fn height( &self ) -> u32 {
+        self.height
+    }
+
+    fn pitch( &self ) -> u32 {
+        self.pitch
+    }
+
+    fn pixels( &self ) -> &[P] {
+        self.pixels
+    }
+
+    fn pixels_mut( &mut self ) -> &mut [P] {
+        self.pixels
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Rect {
+    pub fn new( x: u32, y: u32, width: u32, height: u32 ) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height
+        }
+    }
+
+    // Returns the part of the rectangle which is inside of a `width` x `height` area.
+    fn clip( self, width: u32, height: u32 ) -> Rect {
+        let x = ::std::cmp::min( self.x, width );
+        let y = ::std::cmp::min( self.y, height );
+        Rect {
+            x: x,
+            y: y,
+            width: ::std::cmp::min( self.width, width - x ),
+            height: ::std::cmp::min( self.height, height - y )
+        }
+    }
+}
+
+// Anything outside of the surface is clipped.
+pub fn fill_rect< P: Pixel, S: Surface< P > + ?Sized >( surface: &mut S, rect: Rect, color: P ) {
+    let rect = rect.clip( surface.width(), surface.height() );
+    let pitch = surface.pitch() as usize;
+    let pixels = surface.pixels_mut();
+    for y in rect.y..rect.y + rect.height {
+        let offset = y as usize * pitch + rect.x as usize;
+        for pixel in &mut pixels[ offset..offset + rect.width as usize ] {
+            *pixel = color;
+        }
+    }
+}
+
+// Copies `source_rect` from `source` so that its top left corner ends
+// up at the given position; anything outside of either surface is clipped.
+pub fn blit< P, S, D >( source: &S, source_rect: Rect, destination: &mut D, x: u32, y: u32 )
+    where P: Pixel, S: Surface< P > + ?Sized, D: Surface< P > + ?Sized
+{
+    let source_rect = source_rect.clip( source.width(), source.height() );
+    let destination_rect = Rect::new( x, y, source_rect.width, source_rect.height ).clip( destination.width(), destination.height() );
+
+    let source_pitch = source.pitch() as usize;
+    let destination_pitch = destination.pitch() as usize;
+    let source_pixels = source.pixels();
+    let destination_pixels = destination.pixels_mut();
+    let width = destination_rect.width as usize;
+    for row in 0..destination_rect.height as usize {
+        let source_offset = (source_rect.y as usize + row) * source_pitch + source_rect.x as usize;
+        let destination_offset = (destination_rect.y as usize + row) * destination_pitch + destination_rect.x as usize;
+        destination_pixels[ destination_offset..destination_offset + width ].copy_from_slice( &source_pixels[ source_offset..source_offset + width ] );
+    }
+}
+
+// A set of frames sized according to the `AudioVideoInfo` which are
+// rendered into and presented in turn, so that a core can keep the
+// previous frame around without allocating a new one every frame.
+pub struct FrameBuffers< P: Pixel > {
+    frames: Vec< Frame< P > >,
+    index: usize
+}
+
+impl< P: Pixel > FrameBuffers< P > {
+    pub fn new( av_info: &AudioVideoInfo ) -> FrameBuffers< P > {
+        FrameBuffers::with_count( av_info, 2 )
+    }
+
+    pub fn with_count( av_info: &AudioVideoInfo, count: usize ) -> FrameBuffers< P > {
+        assert!( count > 0 );
+        assert!( P::FORMAT == av_info.pixel_format, "The pixel type doesn't match the pixel format in AudioVideoInfo!" );
+
+        FrameBuffers {
+            frames: (0..count).map( |_| Frame::new( av_info.width, av_info.height ) ).collect(),
+            index: 0
+        }
+    }
+
+    pub fn count( &self ) -> usize {
+        self.frames.len()
+    }
+
+    // The frame which is going to be presented next.
+    pub fn back( &mut self ) -> &mut Frame< P > {
+        &mut self.frames[ self.index ]
+    }
+
+    // The frame which was presented last.
+    pub fn front( &self ) -> &Frame< P > {
+        let index = (self.index + self.frames.len() - 1) % self.frames.len();
+        &self.frames[ index ]
+    }
+
+    pub fn swap( &mut self ) {
+        self.index = (self.index + 1) % self.frames.len();
+    }
+
+    // Uploads the back frame and then moves on to the next one.
+    pub fn present( &mut self, handle: &mut RuntimeHandle ) -> Result< (), UploadError > {
+        handle.upload_frame( &self.frames[ self.index ] )?;
+        self.swap();
+        Ok(())
+    }
+}
+
+pub(crate) fn bytes_per_pixel( format: PixelFormat ) -> usize {
+    match format {
+        PixelFormat::ARGB1555 | PixelFormat::RGB565 => 2,
+        PixelFormat::ARGB8888 => 4
+    }
+}
+
+fn read_pixel( format: PixelFormat, bytes: &[u8] ) -> (u8, u8, u8) {
+    match format {
+        PixelFormat::ARGB1555 => {
+            let value = u16::from_ne_bytes( [bytes[ 0 ], bytes[ 1 ]] );
+            let r = ((value >> 10) & 0x1F) as u8;
+            let g = ((value >> 5) & 0x1F) as u8;
+            let b = (value & 0x1F) as u8;
+            ((r << 3) | (r >> 2), (g << 3) | (g >> 2), (b << 3) | (b >> 2))
+        },
+        PixelFormat::RGB565 => {
+            let value = u16::from_ne_bytes( [bytes[ 0 ], bytes[ 1 ]] );
+            let r = ((value >> 11) & 0x1F) as u8;
+            let g = ((value >> 5) & 0x3F) as u8;
+            let b = (value & 0x1F) as u8;
+            ((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2))
+        },
+        PixelFormat::ARGB8888 => {
+            let value = u32::from_ne_bytes( [bytes[ 0 ], bytes[ 1 ], bytes[ 2 ], bytes[ 3 ]] );
+            ((value >> 16) as u8, (value >> 8) as u8, value as u8)
+        }
+    }
+}
+
+pub(crate) fn write_pixel( format: PixelFormat, (r, g, b): (u8, u8, u8), output: &mut [u8] ) {
+    match format {
+        PixelFormat::ARGB1555 => output.copy_from_slice( &Argb1555::new( r, g, b ).0.to_ne_bytes() ),
+        PixelFormat::RGB565 => output.copy_from_slice( &Rgb565::new( r, g, b ).0.to_ne_bytes() ),
+        PixelFormat::ARGB8888 => output.copy_from_slice( &Argb8888::new( r, g, b ).0.to_ne_bytes() )
+    }
+}
+
+// Used when the frontend doesn't support the core's pixel format;
+// returns the pitch of the converted frame.
+pub(crate) fn convert(
+    input: &[u8],
+    input_format: PixelFormat,
+    input_pitch: usize,
+    output: &mut Vec< u8 >,
+    output_format: PixelFormat,
+    width: u32,
+    height: u32
+) -> usize {
+    let input_bytes_per_pixel = bytes_per_pixel( input_format );
+    let output_bytes_per_pixel = bytes_per_pixel( output_format );
+    let output_pitch = width as usize * output_bytes_per_pixel;
+
+    output.clear();
+    output.resize( output_pitch * height as usize, 0 );
+    for y in 0..height as usize {
+        let input_row = &input[ y * input_pitch..y * input_pitch + width as usize * input_bytes_per_pixel ];
+        let output_row = &mut output[ y * output_pitch..(y + 1) * output_pitch ];
+        for (input, output) in input_row.chunks( input_bytes_per_pixel ).zip( output_row.chunks_mut( output_bytes_per_pixel ) ) {
+            write_pixel( output_format, read_pixel( input_format, input ), output );
+        }
+    }
+
+    output_pitch
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5]
+];
+
+fn dither_channel( value: u8, threshold: u8, bits: u32 ) -> u8 {
+    // Spreads the threshold over the range that gets lost when truncating.
+    let step = 1_u32 << (8 - bits);
+    let offset = (threshold as u32 * step) / 16;
+    let value = ::std::cmp::min( value as u32 + offset, 255 );
+    value as u8
+}
+
+// Converts a 24-bit RGB pixel into RGB565 using a 4x4 ordered dither,
+// which hides the banding that plain truncation would cause.
+pub fn dither_rgb565( x: u32, y: u32, r: u8, g: u8, b: u8 ) -> Rgb565 {
+    let threshold = BAYER_4X4[ (y & 3) as usize ][ (x & 3) as usize ];
+    Rgb565::new(
+        dither_channel( r, threshold, 5 ),
+        dither_channel( g, threshold, 6 ),
+        dither_channel( b, threshold, 5 )
+    )
+}
+
+// Converts a whole frame; the output has to be at least as big as the input.
+pub fn dither_frame( input: &Frame< Argb8888 >, output: &mut Frame< Rgb565 > ) {
+    assert!( output.width() >= input.width() && output.height() >= input.height(), "The output frame is too small!" );
+
+    for y in 0..input.height() {
+        let output_row = output.row_mut( y );
+        for (x, (pixel, output)) in input.row( y ).iter().zip( output_row.iter_mut() ).enumerate() {
+            let value = pixel.0;
+            *output = dither_rgb565( x as u32, y, (value >> 16) as u8, (value >> 8) as u8, value as u8 );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_rgb565_to_argb8888() {
+        let pixels = [Rgb565( 0xFFFF ), Rgb565( 0x0000 ), Rgb565( 0xF800 ), Rgb565( 0x07E0 ), Rgb565( 0x001F )];
+        let input: Vec< u8 > = pixels.iter().flat_map( |pixel| pixel.0.to_ne_bytes().to_vec() ).collect();
+
+        let mut output = Vec::new();
+        let pitch = convert( &input, PixelFormat::RGB565, input.len(), &mut output, PixelFormat::ARGB8888, 5, 1 );
+        assert_eq!( pitch, 20 );
+
+        let output: Vec< u32 > = output.chunks( 4 ).map( |bytes| u32::from_ne_bytes( [bytes[ 0 ], bytes[ 1 ], bytes[ 2 ], bytes[ 3 ]] ) ).collect();
+        assert_eq!( output, vec![ 0xFFFFFF, 0x000000, 0xFF0000, 0x00FF00, 0x0000FF ] );
+    }
+
+    #[test]
+    fn convert_skips_the_padding_of_the_input() {
+        let mut input = Vec::new();
+        for &value in &[0x7C00_u16, 0x03E0, 0xFFFF, 0x001F, 0x0000, 0xFFFF] {
+            input.extend_from_slice( &value.to_ne_bytes() );
+        }
+
+        let mut output = Vec::new();
+        let pitch = convert( &input, PixelFormat::ARGB1555, 6, &mut output, PixelFormat::RGB565, 2, 2 );
+        assert_eq!( pitch, 4 );
+
+        let output: Vec< u16 > = output.chunks( 2 ).map( |bytes| u16::from_ne_bytes( [bytes[ 0 ], bytes[ 1 ]] ) ).collect();
+        assert_eq!( output, vec![ 0xF800, 0x07E0, 0x001F, 0x0000 ] );
+    }
+
+    #[test]
+    fn convert_round_trips_representable_colors() {
+        let colors = [(0x00, 0x00, 0x00), (0xFF, 0xFF, 0xFF), (0x84, 0x82, 0x84), (0x08, 0x04, 0x10)];
+        for &color in &colors {
+            let mut input = [0; 4];
+            write_pixel( PixelFormat::ARGB8888, color, &mut input );
+
+            let mut rgb565 = Vec::new();
+            convert( &input, PixelFormat::ARGB8888, 4, &mut rgb565, PixelFormat::RGB565, 1, 1 );
+            let mut output = Vec::new();
+            convert( &rgb565, PixelFormat::RGB565, 2, &mut output, PixelFormat::ARGB8888, 1, 1 );
+            assert_eq!( read_pixel( PixelFormat::ARGB8888, &output ), color );
+        }
+    }
+
+    #[test]
+    fn dither_keeps_black_and_white() {
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!( dither_rgb565( x, y, 0, 0, 0 ), Rgb565( 0x0000 ) );
+                assert_eq!( dither_rgb565( x, y, 255, 255, 255 ), Rgb565( 0xFFFF ) );
+            }
+        }
+    }
+
+    #[test]
+    fn dither_averages_out_over_a_block() {
+        // Halfway between the first two levels of a 5-bit channel.
+        let mut count = 0;
+        for y in 0..4 {
+            for x in 0..4 {
+                let pixel = dither_rgb565( x, y, 4, 0, 0 );
+                assert!( pixel == Rgb565( 0x0000 ) || pixel == Rgb565( 0x0800 ) );
+                if pixel == Rgb565( 0x0800 ) {
+                    count += 1;
+                }
+            }
+        }
+
+        assert_eq!( count, 8 );
+    }
+
+    #[test]
+    fn dither_frame_uses_the_pixel_position() {
+        let mut input = Frame::new( 4, 4 );
+        input.fill( Argb8888::new( 4, 0, 0 ) );
+        let mut output = Frame::with_pitch( 5, 5, 6 );
+        dither_frame( &input, &mut output );
+
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!( output[ (x, y) ], dither_rgb565( x, y, 4, 0, 0 ) );
+            }
+            assert_eq!( output[ (4, y) ], Rgb565( 0 ) );
+        }
+    }
+
+    fn numbered( width: u32, height: u32, pitch: u32 ) -> Frame< Argb8888 > {
+        let mut frame = Frame::with_pitch( width, height, pitch );
+        for y in 0..height {
+            for x in 0..width {
+                frame[ (x, y) ] = Argb8888( y * 10 + x + 1 );
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn blit_between_different_pitches() {
+        let source = numbered( 3, 3, 5 );
+        let mut destination = Frame::with_pitch( 4, 4, 7 );
+        blit( &source, Rect::new( 1, 1, 2, 2 ), &mut destination, 2, 1 );
+
+        for y in 0..4 {
+            let row: Vec< u32 > = destination.row( y ).iter().map( |pixel| pixel.0 ).collect();
+            let expected = match y {
+                1 => vec![ 0, 0, 12, 13 ],
+                2 => vec![ 0, 0, 22, 23 ],
+                _ => vec![ 0, 0, 0, 0 ]
+            };
+            assert_eq!( row, expected );
+        }
+
+        // The padding past the width is never touched.
+        assert_eq!( destination.pixels()[ 7 + 4..7 + 7 ], [Argb8888( 0 ); 3] );
+    }
+
+    #[test]
+    fn blit_clips_to_both_surfaces() {
+        let source = numbered( 3, 3, 3 );
+        let mut destination = Frame::new( 3, 3 );
+        blit( &source, Rect::new( 1, 0, 10, 10 ), &mut destination, 2, 2 );
+
+        let pixels: Vec< u32 > = destination.pixels().iter().map( |pixel| pixel.0 ).collect();
+        assert_eq!( pixels, vec![ 0, 0, 0, 0, 0, 0, 0, 0, 2 ] );
+
+        blit( &source, Rect::new( 5, 5, 1, 1 ), &mut destination, 0, 0 );
+        blit( &source, Rect::new( 0, 0, 1, 1 ), &mut destination, 3, 0 );
+        assert_eq!( destination.pixels()[ 0 ], Argb8888( 0 ) );
+    }
+
+    #[test]
+    fn fill_rect_is_clipped() {
+        let mut frame = Frame::with_pitch( 3, 2, 4 );
+        fill_rect( &mut frame, Rect::new( 1, 1, 10, 10 ), Rgb565( 1 ) );
+        assert_eq!( frame.pixels(), &[Rgb565( 0 ), Rgb565( 0 ), Rgb565( 0 ), Rgb565( 0 ), Rgb565( 0 ), Rgb565( 1 ), Rgb565( 1 ), Rgb565( 0 )] );
+
+        fill_rect( &mut frame, Rect::new( 3, 0, 1, 1 ), Rgb565( 2 ) );
+        assert!( frame.pixels().iter().all( |&pixel| pixel != Rgb565( 2 ) ) );
+    }
+}
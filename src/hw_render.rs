@@ -0,0 +1,727 @@
+use std::ptr;
+use std::ffi::CString;
+
+use libc;
+use libretro_sys;
+
+use environment::{call_environment, call_environment_mut, EnvError};
+use interfaces;
+use lock;
+
+// Not exposed by libretro-sys yet.
+const ENVIRONMENT_SET_HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE: libc::c_uint = 43 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+
+const HW_CONTEXT_OPENGL: libc::c_uint = 1;
+const HW_CONTEXT_OPENGLES2: libc::c_uint = 2;
+const HW_CONTEXT_OPENGL_CORE: libc::c_uint = 3;
+const HW_CONTEXT_OPENGLES3: libc::c_uint = 4;
+const HW_CONTEXT_OPENGLES_VERSION: libc::c_uint = 5;
+const HW_CONTEXT_VULKAN: libc::c_uint = 6;
+const HW_CONTEXT_DIRECT3D11: libc::c_uint = 7;
+const HW_CONTEXT_DIRECT3D12: libc::c_uint = 9;
+
+const HW_RENDER_INTERFACE_VULKAN: libc::c_uint = 0;
+const HW_RENDER_INTERFACE_VULKAN_VERSION: libc::c_uint = 5;
+const HW_RENDER_INTERFACE_DIRECT3D11: libc::c_uint = 3;
+const HW_RENDER_INTERFACE_DIRECT3D11_VERSION: libc::c_uint = 1;
+const HW_RENDER_INTERFACE_DIRECT3D12: libc::c_uint = 4;
+const HW_RENDER_INTERFACE_DIRECT3D12_VERSION: libc::c_uint = 1;
+const HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE_VULKAN: libc::c_uint = 0;
+const HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE_VULKAN_VERSION: libc::c_uint = 1;
+
+const VK_STRUCTURE_TYPE_APPLICATION_INFO: i32 = 0;
+const VK_API_VERSION_1_0: u32 = 1 << 22;
+
+pub type ProcAddress = Option< unsafe extern "C" fn() >;
+
+#[repr(C)]
+struct RawHwRenderCallback {
+    context_type: libc::c_uint,
+    context_reset: Option< unsafe extern "C" fn() >,
+    get_current_framebuffer: Option< unsafe extern "C" fn() -> libc::uintptr_t >,
+    get_proc_address: Option< unsafe extern "C" fn( symbol: *const libc::c_char ) -> ProcAddress >,
+    depth: bool,
+    stencil: bool,
+    bottom_left_origin: bool,
+    version_major: libc::c_uint,
+    version_minor: libc::c_uint,
+    cache_context: bool,
+    context_destroy: Option< unsafe extern "C" fn() >,
+    debug_context: bool
+}
+
+#[repr(C)]
+struct RawVulkanApplicationInfo {
+    structure_type: i32,
+    next: *const libc::c_void,
+    application_name: *const libc::c_char,
+    application_version: u32,
+    engine_name: *const libc::c_char,
+    engine_version: u32,
+    api_version: u32
+}
+
+pub type VulkanCreateDeviceFn = unsafe extern "C" fn(
+    context: *mut libc::c_void,
+    instance: *mut libc::c_void,
+    gpu: *mut libc::c_void,
+    surface: u64,
+    get_instance_proc_addr: ProcAddress,
+    required_device_extensions: *const *const libc::c_char,
+    num_required_device_extensions: libc::c_uint,
+    required_device_layers: *const *const libc::c_char,
+    num_required_device_layers: libc::c_uint,
+    required_features: *const libc::c_void
+) -> bool;
+
+pub type VulkanDestroyDeviceFn = unsafe extern "C" fn();
+
+#[repr(C)]
+struct RawVulkanNegotiationInterface {
+    interface_type: libc::c_uint,
+    interface_version: libc::c_uint,
+    get_application_info: Option< unsafe extern "C" fn() -> *const RawVulkanApplicationInfo >,
+    create_device: Option< VulkanCreateDeviceFn >,
+    destroy_device: Option< VulkanDestroyDeviceFn >
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct VulkanComponentMapping {
+    pub r: i32,
+    pub g: i32,
+    pub b: i32,
+    pub a: i32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct VulkanImageSubresourceRange {
+    pub aspect_mask: u32,
+    pub base_mip_level: u32,
+    pub level_count: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32
+}
+
+// Mirrors VkImageViewCreateInfo.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct VulkanImageViewCreateInfo {
+    pub structure_type: i32,
+    pub next: *const libc::c_void,
+    pub flags: u32,
+    pub image: u64,
+    pub view_type: i32,
+    pub format: i32,
+    pub components: VulkanComponentMapping,
+    pub subresource_range: VulkanImageSubresourceRange
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct VulkanImage {
+    pub image_view: u64,
+    pub image_layout: i32,
+    pub create_info: VulkanImageViewCreateInfo
+}
+
+#[repr(C)]
+struct RawVulkanInterface {
+    interface_type: libc::c_uint,
+    interface_version: libc::c_uint,
+    handle: *mut libc::c_void,
+    instance: *mut libc::c_void,
+    gpu: *mut libc::c_void,
+    device: *mut libc::c_void,
+    get_device_proc_addr: ProcAddress,
+    get_instance_proc_addr: ProcAddress,
+    queue: *mut libc::c_void,
+    queue_index: libc::c_uint,
+    set_image: unsafe extern "C" fn( handle: *mut libc::c_void, image: *const VulkanImage, num_semaphores: u32, semaphores: *const u64, src_queue_family: u32 ),
+    get_sync_index: unsafe extern "C" fn( handle: *mut libc::c_void ) -> u32,
+    get_sync_index_mask: unsafe extern "C" fn( handle: *mut libc::c_void ) -> u32,
+    set_command_buffers: unsafe extern "C" fn( handle: *mut libc::c_void, num_cmd: u32, cmd: *const *mut libc::c_void ),
+    wait_sync_index: unsafe extern "C" fn( handle: *mut libc::c_void ),
+    lock_queue: unsafe extern "C" fn( handle: *mut libc::c_void ),
+    unlock_queue: unsafe extern "C" fn( handle: *mut libc::c_void ),
+    set_signal_semaphore: unsafe extern "C" fn( handle: *mut libc::c_void, semaphore: u64 )
+}
+
+#[repr(C)]
+struct RawInterfaceHeader {
+    interface_type: libc::c_uint,
+    interface_version: libc::c_uint
+}
+
+#[repr(C)]
+struct RawDirect3D11Interface {
+    interface_type: libc::c_uint,
+    interface_version: libc::c_uint,
+    handle: *mut libc::c_void,
+    device: *mut libc::c_void,
+    context: *mut libc::c_void,
+    feature_level: libc::c_int,
+    d3d_compile: *mut libc::c_void
+}
+
+#[repr(C)]
+struct RawDirect3D12Interface {
+    interface_type: libc::c_uint,
+    interface_version: libc::c_uint,
+    handle: *mut libc::c_void,
+    device: *mut libc::c_void,
+    queue: *mut libc::c_void,
+    d3d_compile: *mut libc::c_void,
+    set_texture: unsafe extern "C" fn( handle: *mut libc::c_void, texture: *mut libc::c_void, format: libc::c_int )
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum ContextType {
+    OpenGl,
+    OpenGlCore,
+    OpenGlEs2,
+    OpenGlEs3,
+    OpenGlEsVersion,
+    Vulkan,
+    Direct3D11,
+    Direct3D12
+}
+
+impl ContextType {
+    fn is_opengl( self ) -> bool {
+        match self {
+            ContextType::OpenGl |
+            ContextType::OpenGlCore |
+            ContextType::OpenGlEs2 |
+            ContextType::OpenGlEs3 |
+            ContextType::OpenGlEsVersion => true,
+            ContextType::Vulkan |
+            ContextType::Direct3D11 |
+            ContextType::Direct3D12 => false
+        }
+    }
+}
+
+pub struct VulkanApplicationInfo {
+    application_name: CString,
+    application_version: u32,
+    engine_name: CString,
+    engine_version: u32,
+    api_version: u32
+}
+
+impl VulkanApplicationInfo {
+    pub fn new( application_name: &str, application_version: u32, api_version: u32 ) -> VulkanApplicationInfo {
+        VulkanApplicationInfo {
+            application_name: CString::new( application_name ).unwrap(),
+            application_version: application_version,
+            engine_name: CString::new( "" ).unwrap(),
+            engine_version: 0,
+            api_version: api_version
+        }
+    }
+
+    pub fn engine( mut self, engine_name: &str, engine_version: u32 ) -> Self {
+        self.engine_name = CString::new( engine_name ).unwrap();
+        self.engine_version = engine_version;
+        self
+    }
+}
+
+pub struct HardwareContext {
+    context_type: ContextType,
+    version_major: u32,
+    version_minor: u32,
+    depth: bool,
+    stencil: bool,
+    bottom_left_origin: bool,
+    cache_context: bool,
+    debug_context: bool,
+    application_info: Option< VulkanApplicationInfo >,
+    create_device: Option< VulkanCreateDeviceFn >,
+    destroy_device: Option< VulkanDestroyDeviceFn >
+}
+
+impl HardwareContext {
+    fn new( context_type: ContextType, version_major: u32, version_minor: u32 ) -> HardwareContext {
+        HardwareContext {
+            context_type: context_type,
+            version_major: version_major,
+            version_minor: version_minor,
+            depth: false,
+            stencil: false,
+            bottom_left_origin: false,
+            cache_context: false,
+            debug_context: false,
+            application_info: None,
+            create_device: None,
+            destroy_device: None
+        }
+    }
+
+    // A compatibility profile context.
+    pub fn opengl() -> HardwareContext {
+        HardwareContext::new( ContextType::OpenGl, 0, 0 )
+    }
+
+    pub fn opengl_core( version_major: u32, version_minor: u32 ) -> HardwareContext {
+        HardwareContext::new( ContextType::OpenGlCore, version_major, version_minor )
+    }
+
+    pub fn opengles2() -> HardwareContext {
+        HardwareContext::new( ContextType::OpenGlEs2, 2, 0 )
+    }
+
+    pub fn opengles3() -> HardwareContext {
+        HardwareContext::new( ContextType::OpenGlEs3, 3, 0 )
+    }
+
+    // For GLES 3.1 and newer.
+    pub fn opengles( version_major: u32, version_minor: u32 ) -> HardwareContext {
+        HardwareContext::new( ContextType::OpenGlEsVersion, version_major, version_minor )
+    }
+
+    pub fn vulkan() -> HardwareContext {
+        HardwareContext::new( ContextType::Vulkan, VK_API_VERSION_1_0, 0 )
+    }
+
+    pub fn direct3d11() -> HardwareContext {
+        HardwareContext::new( ContextType::Direct3D11, 11, 0 )
+    }
+
+    pub fn direct3d12() -> HardwareContext {
+        HardwareContext::new( ContextType::Direct3D12, 12, 0 )
+    }
+
+    pub fn depth( mut self ) -> Self {
+        self.depth = true;
+        self
+    }
+
+    pub fn stencil( mut self ) -> Self {
+        self.stencil = true;
+        self
+    }
+
+    pub fn bottom_left_origin( mut self ) -> Self {
+        self.bottom_left_origin = true;
+        self
+    }
+
+    // Asks the frontend to keep the context alive across
+    // resets instead of destroying and recreating it.
+    pub fn cache_context( mut self ) -> Self {
+        self.cache_context = true;
+        self
+    }
+
+    pub fn debug_context( mut self ) -> Self {
+        self.debug_context = true;
+        self
+    }
+
+    pub fn vulkan_application_info( mut self, application_info: VulkanApplicationInfo ) -> Self {
+        assert_eq!( self.context_type, ContextType::Vulkan, "Application info can only be specified for Vulkan contexts!" );
+        self.application_info = Some( application_info );
+        self
+    }
+
+    // For cores which need full control over how the Vulkan device is created;
+    // when not given the frontend creates the device itself.
+    pub unsafe fn vulkan_device_callbacks( mut self, create_device: VulkanCreateDeviceFn, destroy_device: Option< VulkanDestroyDeviceFn > ) -> Self {
+        assert_eq!( self.context_type, ContextType::Vulkan, "Device callbacks can only be specified for Vulkan contexts!" );
+        self.create_device = Some( create_device );
+        self.destroy_device = destroy_device;
+        self
+    }
+}
+
+pub(crate) struct ContextHooks {
+    pub instance: *mut libc::c_void,
+    pub on_reset: unsafe fn( instance: *mut libc::c_void ),
+    pub on_destroy: unsafe fn( instance: *mut libc::c_void )
+}
+
+pub(crate) struct State {
+    context_type: ContextType,
+    hooks: ContextHooks,
+    get_current_framebuffer: Option< unsafe extern "C" fn() -> libc::uintptr_t >,
+    get_proc_address: Option< unsafe extern "C" fn( symbol: *const libc::c_char ) -> ProcAddress >,
+    interface: *const RawInterfaceHeader,
+
+    // The frontend keeps pointers to these around.
+    negotiation_interface: Option< Box< RawVulkanNegotiationInterface > >,
+    application_info: Option< (VulkanApplicationInfo, Box< RawVulkanApplicationInfo >) >
+}
+
+// Everything in here is either owned by us or handed out by the frontend.
+unsafe impl Send for State {}
+
+// Every instance has its own context; the frontend's callbacks end up with
+// the one it's currently inside of, or the most recently initialized one.
+fn with_state< R, F: FnOnce( &mut Option< State > ) -> R >( callback: F ) -> R {
+    interfaces::with( |interfaces| callback( &mut *lock( &interfaces.hw_render ) ) )
+}
+
+// The core's hooks are called without holding the lock, since
+// they're free to call back into this module.
+unsafe extern "C" fn context_reset() {
+    let hook = with_state( |state| {
+        let state = state.as_mut()?;
+        if state.context_type.is_opengl() == false {
+            let mut interface: *const RawInterfaceHeader = ptr::null();
+            state.interface = match call_environment_mut( libretro_sys::ENVIRONMENT_GET_HW_RENDER_INTERFACE, &mut interface ) {
+                Ok(()) => interface,
+                Err( _ ) => ptr::null()
+            };
+        }
+
+        Some( (state.hooks.instance, state.hooks.on_reset) )
+    });
+
+    if let Some( (instance, on_reset) ) = hook {
+        let _ = ::catch_panic( || on_reset( instance ) );
+    }
+}
+
+unsafe extern "C" fn context_destroy() {
+    let hook = with_state( |state| {
+        let state = state.as_mut()?;
+        state.interface = ptr::null();
+        Some( (state.hooks.instance, state.hooks.on_destroy) )
+    });
+
+    if let Some( (instance, on_destroy) ) = hook {
+        let _ = ::catch_panic( || on_destroy( instance ) );
+    }
+}
+
+unsafe fn interface< T >( interface_type: libc::c_uint, minimum_version: libc::c_uint ) -> Option< &'static T > {
+    let interface = with_state( |state| {
+        match *state {
+            Some( ref state ) => state.interface,
+            None => ptr::null()
+        }
+    });
+
+    if interface.is_null() {
+        return None;
+    }
+
+    let header = &*interface;
+    if header.interface_type != interface_type || header.interface_version < minimum_version {
+        return None;
+    }
+
+    Some( &*(interface as *const T) )
+}
+
+unsafe extern "C" fn get_application_info() -> *const RawVulkanApplicationInfo {
+    with_state( |state| {
+        match *state {
+            Some( State { application_info: Some( (_, ref raw) ), .. } ) => &**raw as *const RawVulkanApplicationInfo,
+            _ => ptr::null()
+        }
+    })
+}
+
+pub(crate) fn register( context: HardwareContext, hooks: ContextHooks ) -> Result< (), EnvError > {
+    let context_type = match context.context_type {
+        ContextType::OpenGl => HW_CONTEXT_OPENGL,
+        ContextType::OpenGlCore => HW_CONTEXT_OPENGL_CORE,
+        ContextType::OpenGlEs2 => HW_CONTEXT_OPENGLES2,
+        ContextType::OpenGlEs3 => HW_CONTEXT_OPENGLES3,
+        ContextType::OpenGlEsVersion => HW_CONTEXT_OPENGLES_VERSION,
+        ContextType::Vulkan => HW_CONTEXT_VULKAN,
+        ContextType::Direct3D11 => HW_CONTEXT_DIRECT3D11,
+        ContextType::Direct3D12 => HW_CONTEXT_DIRECT3D12
+    };
+
+    // For Vulkan this is the full packed API version.
+    let version_major = match context.application_info {
+        Some( ref info ) if context.context_type == ContextType::Vulkan => info.api_version,
+        _ => context.version_major
+    };
+
+    let mut callback = RawHwRenderCallback {
+        context_type: context_type,
+        context_reset: Some( context_reset ),
+        get_current_framebuffer: None,
+        get_proc_address: None,
+        depth: context.depth,
+        stencil: context.stencil,
+        bottom_left_origin: context.bottom_left_origin,
+        version_major: version_major as libc::c_uint,
+        version_minor: context.version_minor as libc::c_uint,
+        cache_context: context.cache_context,
+        context_destroy: Some( context_destroy ),
+        debug_context: context.debug_context
+    };
+
+    unsafe {
+        call_environment_mut( libretro_sys::ENVIRONMENT_SET_HW_RENDER, &mut callback )?;
+
+        let application_info = context.application_info.map( |info| {
+            let raw = Box::new( RawVulkanApplicationInfo {
+                structure_type: VK_STRUCTURE_TYPE_APPLICATION_INFO,
+                next: ptr::null(),
+                application_name: info.application_name.as_ptr(),
+                application_version: info.application_version,
+                engine_name: info.engine_name.as_ptr(),
+                engine_version: info.engine_version,
+                api_version: info.api_version
+            });
+
+            (info, raw)
+        });
+
+        let negotiation_interface = if context.context_type == ContextType::Vulkan {
+            let interface = Box::new( RawVulkanNegotiationInterface {
+                interface_type: HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE_VULKAN,
+                interface_version: HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE_VULKAN_VERSION,
+                get_application_info: if application_info.is_some() { Some( get_application_info ) } else { None },
+                create_device: context.create_device,
+                destroy_device: context.destroy_device
+            });
+
+            Some( interface )
+        } else {
+            None
+        };
+
+        let state = State {
+            context_type: context.context_type,
+            hooks: hooks,
+            get_current_framebuffer: callback.get_current_framebuffer,
+            get_proc_address: callback.get_proc_address,
+            interface: ptr::null(),
+            negotiation_interface: negotiation_interface,
+            application_info: application_info
+        };
+
+        // The interface is boxed, so it stays put; the lock can't be held
+        // here since the frontend might ask for the application info.
+        let negotiation_interface = with_state( |slot| {
+            *slot = Some( state );
+            match *slot {
+                Some( State { negotiation_interface: Some( ref interface ), .. } ) => Some( &**interface as *const RawVulkanNegotiationInterface ),
+                _ => None
+            }
+        });
+
+        if let Some( interface ) = negotiation_interface {
+            let _ = call_environment( ENVIRONMENT_SET_HW_RENDER_CONTEXT_NEGOTIATION_INTERFACE, &*interface );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_proc_address( symbol: &str ) -> ProcAddress {
+    let symbol = match CString::new( symbol ) {
+        Ok( symbol ) => symbol,
+        Err( _ ) => return None
+    };
+
+    let get_proc_address = match with_state( |state| state.as_ref().and_then( |state| state.get_proc_address ) ) {
+        Some( get_proc_address ) => get_proc_address,
+        None => return None
+    };
+
+    unsafe { get_proc_address( symbol.as_ptr() ) }
+}
+
+#[cfg(feature = "glow")]
+fn is_opengl() -> bool {
+    with_state( |state| {
+        match *state {
+            Some( ref state ) => state.context_type.is_opengl(),
+            None => false
+        }
+    })
+}
+
+#[cfg(feature = "glow")]
+pub fn create_glow_context() -> Option< ::glow::Context > {
+    if is_opengl() == false {
+        return None;
+    }
+
+    let get_proc_address = match with_state( |state| state.as_ref().and_then( |state| state.get_proc_address ) ) {
+        Some( get_proc_address ) => get_proc_address,
+        None => return None
+    };
+
+    unsafe {
+        let context = ::glow::Context::from_loader_function_cstr( |symbol| {
+            match get_proc_address( symbol.as_ptr() ) {
+                Some( address ) => address as *const libc::c_void,
+                None => ptr::null()
+            }
+        });
+
+        Some( context )
+    }
+}
+
+// The framebuffer object the core should render into; only valid
+// while a frame is being rendered and may change between frames.
+pub fn current_framebuffer() -> usize {
+    let get_current_framebuffer = match with_state( |state| state.as_ref().and_then( |state| state.get_current_framebuffer ) ) {
+        Some( get_current_framebuffer ) => get_current_framebuffer,
+        None => return 0
+    };
+
+    unsafe { get_current_framebuffer() as usize }
+}
+
+#[derive(Copy, Clone)]
+pub struct VulkanInterface {
+    interface: &'static RawVulkanInterface
+}
+
+impl VulkanInterface {
+    pub fn instance( &self ) -> *mut libc::c_void {
+        self.interface.instance
+    }
+
+    pub fn gpu( &self ) -> *mut libc::c_void {
+        self.interface.gpu
+    }
+
+    pub fn device( &self ) -> *mut libc::c_void {
+        self.interface.device
+    }
+
+    pub fn queue( &self ) -> *mut libc::c_void {
+        self.interface.queue
+    }
+
+    pub fn queue_index( &self ) -> u32 {
+        self.interface.queue_index as u32
+    }
+
+    pub fn get_device_proc_addr( &self ) -> ProcAddress {
+        self.interface.get_device_proc_addr
+    }
+
+    pub fn get_instance_proc_addr( &self ) -> ProcAddress {
+        self.interface.get_instance_proc_addr
+    }
+
+    pub unsafe fn set_image( &self, image: &VulkanImage, semaphores: &[u64], src_queue_family: u32 ) {
+        (self.interface.set_image)( self.interface.handle, image, semaphores.len() as u32, semaphores.as_ptr(), src_queue_family );
+    }
+
+    pub fn sync_index( &self ) -> u32 {
+        unsafe {
+            (self.interface.get_sync_index)( self.interface.handle )
+        }
+    }
+
+    pub fn sync_index_mask( &self ) -> u32 {
+        unsafe {
+            (self.interface.get_sync_index_mask)( self.interface.handle )
+        }
+    }
+
+    pub unsafe fn set_command_buffers( &self, command_buffers: &[*mut libc::c_void] ) {
+        (self.interface.set_command_buffers)( self.interface.handle, command_buffers.len() as u32, command_buffers.as_ptr() );
+    }
+
+    pub fn wait_sync_index( &self ) {
+        unsafe {
+            (self.interface.wait_sync_index)( self.interface.handle );
+        }
+    }
+
+    pub fn lock_queue( &self ) {
+        unsafe {
+            (self.interface.lock_queue)( self.interface.handle );
+        }
+    }
+
+    pub fn unlock_queue( &self ) {
+        unsafe {
+            (self.interface.unlock_queue)( self.interface.handle );
+        }
+    }
+
+    pub unsafe fn set_signal_semaphore( &self, semaphore: u64 ) {
+        (self.interface.set_signal_semaphore)( self.interface.handle, semaphore );
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Direct3D11Interface {
+    interface: &'static RawDirect3D11Interface
+}
+
+impl Direct3D11Interface {
+    // ID3D11Device*
+    pub fn device( &self ) -> *mut libc::c_void {
+        self.interface.device
+    }
+
+    // ID3D11DeviceContext*
+    pub fn context( &self ) -> *mut libc::c_void {
+        self.interface.context
+    }
+
+    // D3D_FEATURE_LEVEL
+    pub fn feature_level( &self ) -> i32 {
+        self.interface.feature_level as i32
+    }
+
+    // pD3DCompile
+    pub fn d3d_compile( &self ) -> *mut libc::c_void {
+        self.interface.d3d_compile
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Direct3D12Interface {
+    interface: &'static RawDirect3D12Interface
+}
+
+impl Direct3D12Interface {
+    // ID3D12Device*
+    pub fn device( &self ) -> *mut libc::c_void {
+        self.interface.device
+    }
+
+    // ID3D12CommandQueue*
+    pub fn queue( &self ) -> *mut libc::c_void {
+        self.interface.queue
+    }
+
+    // pD3DCompile
+    pub fn d3d_compile( &self ) -> *mut libc::c_void {
+        self.interface.d3d_compile
+    }
+
+    // Takes an ID3D12Resource* and a DXGI_FORMAT.
+    pub unsafe fn set_texture( &self, texture: *mut libc::c_void, format: i32 ) {
+        (self.interface.set_texture)( self.interface.handle, texture, format as libc::c_int );
+    }
+}
+
+// These are only available after the frontend has reset the context.
+pub fn vulkan() -> Option< VulkanInterface > {
+    unsafe {
+        interface( HW_RENDER_INTERFACE_VULKAN, HW_RENDER_INTERFACE_VULKAN_VERSION ).map( |interface| VulkanInterface { interface: interface } )
+    }
+}
+
+pub fn direct3d11() -> Option< Direct3D11Interface > {
+    unsafe {
+        interface( HW_RENDER_INTERFACE_DIRECT3D11, HW_RENDER_INTERFACE_DIRECT3D11_VERSION ).map( |interface| Direct3D11Interface { interface: interface } )
+    }
+}
+
+pub fn direct3d12() -> Option< Direct3D12Interface > {
+    unsafe {
+        interface( HW_RENDER_INTERFACE_DIRECT3D12, HW_RENDER_INTERFACE_DIRECT3D12_VERSION ).map( |interface| Direct3D12Interface { interface: interface } )
+    }
+}
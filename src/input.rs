@@ -0,0 +1,605 @@
+use std::mem;
+use std::ptr;
+use std::cmp::max;
+use std::ffi::CString;
+
+use libc;
+use libretro_sys;
+
+use environment::call_environment;
+use keyboard::{Key, KeyModifiers};
+use interfaces;
+use lock;
+use {Core, Retro, RuntimeHandle};
+
+// Not exposed by libretro-sys yet.
+const DEVICE_INDEX_ANALOG_BUTTON: libc::c_uint = 2;
+const DEVICE_ID_POINTER_COUNT: libc::c_uint = 3;
+const DEVICE_ID_JOYPAD_MASK: libc::c_uint = 256;
+pub(crate) const ENVIRONMENT_GET_INPUT_BITMASKS: libc::c_uint = 51 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+pub(crate) const ENVIRONMENT_GET_INPUT_MAX_USERS: libc::c_uint = 61;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum DeviceType {
+    None,
+    Joypad,
+    Mouse,
+    Keyboard,
+    Lightgun,
+    Analog,
+    Pointer
+}
+
+impl DeviceType {
+    pub fn id( self ) -> u32 {
+        let id = match self {
+            DeviceType::None => libretro_sys::DEVICE_NONE,
+            DeviceType::Joypad => libretro_sys::DEVICE_JOYPAD,
+            DeviceType::Mouse => libretro_sys::DEVICE_MOUSE,
+            DeviceType::Keyboard => libretro_sys::DEVICE_KEYBOARD,
+            DeviceType::Lightgun => libretro_sys::DEVICE_LIGHTGUN,
+            DeviceType::Analog => libretro_sys::DEVICE_ANALOG,
+            DeviceType::Pointer => libretro_sys::DEVICE_POINTER
+        };
+
+        id as u32
+    }
+
+    pub fn subclass( self, index: u32 ) -> u32 {
+        ((index + 1) << libretro_sys::DEVICE_TYPE_SHIFT) | self.id()
+    }
+
+    pub fn from_id( id: u32 ) -> Option< DeviceType > {
+        match id & libretro_sys::DEVICE_MASK as u32 {
+            0 => Some( DeviceType::None ),
+            1 => Some( DeviceType::Joypad ),
+            2 => Some( DeviceType::Mouse ),
+            3 => Some( DeviceType::Keyboard ),
+            4 => Some( DeviceType::Lightgun ),
+            5 => Some( DeviceType::Analog ),
+            6 => Some( DeviceType::Pointer ),
+            _ => None
+        }
+    }
+}
+
+#[repr(C)]
+struct RawControllerDescription {
+    description: *const libc::c_char,
+    id: libc::c_uint
+}
+
+#[repr(C)]
+struct RawControllerInfo {
+    types: *const RawControllerDescription,
+    count: libc::c_uint
+}
+
+pub struct ControllerPort {
+    descriptions: Vec< CString >,
+    raw: Vec< RawControllerDescription >
+}
+
+impl ControllerPort {
+    pub fn new() -> ControllerPort {
+        ControllerPort {
+            descriptions: Vec::new(),
+            raw: Vec::new()
+        }
+    }
+
+    pub fn device( mut self, description: &str, id: u32 ) -> Self {
+        let description = CString::new( description ).unwrap();
+        self.raw.push( RawControllerDescription {
+            description: description.as_ptr(),
+            id: id as libc::c_uint
+        });
+        self.descriptions.push( description );
+        self
+    }
+}
+
+pub struct ControllerInfo {
+    ports: Vec< ControllerPort >,
+
+    // The frontend holds on to these pointers, so they're built
+    // up front and kept alive as long as the CoreInfo is.
+    raw: Vec< RawControllerInfo >
+}
+
+impl ControllerInfo {
+    pub fn new() -> ControllerInfo {
+        ControllerInfo {
+            ports: Vec::new(),
+            raw: vec![ RawControllerInfo { types: ptr::null(), count: 0 } ]
+        }
+    }
+
+    pub fn port( mut self, port: ControllerPort ) -> Self {
+        let terminator = self.raw.pop().unwrap();
+        self.raw.push( RawControllerInfo {
+            types: port.raw.as_ptr(),
+            count: port.raw.len() as libc::c_uint
+        });
+        self.raw.push( terminator );
+        self.ports.push( port );
+        self
+    }
+
+    pub(crate) fn port_count( &self ) -> usize {
+        self.ports.len()
+    }
+
+    pub(crate) fn register( &self ) {
+        if self.ports.is_empty() {
+            return;
+        }
+
+        unsafe {
+            let _ = call_environment( libretro_sys::ENVIRONMENT_SET_CONTROLLER_INFO, &self.raw[ 0 ] );
+        }
+    }
+}
+
+struct InputDescriptor {
+    port: libc::c_uint,
+    device: libc::c_uint,
+    index: libc::c_uint,
+    id: libc::c_uint,
+    description: CString
+}
+
+pub struct InputDescriptors {
+    descriptors: Vec< InputDescriptor >
+}
+
+impl InputDescriptors {
+    pub fn new() -> InputDescriptors {
+        InputDescriptors {
+            descriptors: Vec::new()
+        }
+    }
+
+    fn push( mut self, port: u32, device: libc::c_uint, index: libc::c_uint, id: libc::c_uint, description: &str ) -> Self {
+        self.descriptors.push( InputDescriptor {
+            port: port as libc::c_uint,
+            device: device,
+            index: index,
+            id: id,
+            description: CString::new( description ).unwrap()
+        });
+        self
+    }
+
+    pub fn joypad_button( self, port: u32, button: JoypadButton, description: &str ) -> Self {
+        self.push( port, libretro_sys::DEVICE_JOYPAD, 0, joypad_button_device_id( button ), description )
+    }
+
+    pub fn analog_stick( self, port: u32, stick: AnalogStick, description_x: &str, description_y: &str ) -> Self {
+        let index = analog_stick_index( stick );
+        self.push( port, libretro_sys::DEVICE_ANALOG, index, libretro_sys::DEVICE_ID_ANALOG_X, description_x )
+            .push( port, libretro_sys::DEVICE_ANALOG, index, libretro_sys::DEVICE_ID_ANALOG_Y, description_y )
+    }
+
+    pub(crate) fn register( &self ) {
+        if self.descriptors.is_empty() {
+            return;
+        }
+
+        let mut descriptors: Vec< libretro_sys::InputDescriptor > = self.descriptors.iter().map( |descriptor| {
+            libretro_sys::InputDescriptor {
+                port: descriptor.port,
+                device: descriptor.device,
+                index: descriptor.index,
+                id: descriptor.id,
+                description: descriptor.description.as_ptr()
+            }
+        }).collect();
+
+        descriptors.push( libretro_sys::InputDescriptor {
+            port: 0,
+            device: 0,
+            index: 0,
+            id: 0,
+            description: ptr::null()
+        });
+
+        unsafe {
+            let _ = call_environment( libretro_sys::ENVIRONMENT_SET_INPUT_DESCRIPTORS, &descriptors[ 0 ] );
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum JoypadButton {
+    A,
+    B,
+    X,
+    Y,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+    L1,
+    L2,
+    L3,
+    R1,
+    R2,
+    R3
+}
+
+const JOYPAD_BUTTONS: [JoypadButton; 16] = [
+    JoypadButton::A,
+    JoypadButton::B,
+    JoypadButton::X,
+    JoypadButton::Y,
+    JoypadButton::Select,
+    JoypadButton::Start,
+    JoypadButton::Up,
+    JoypadButton::Down,
+    JoypadButton::Left,
+    JoypadButton::Right,
+    JoypadButton::L1,
+    JoypadButton::L2,
+    JoypadButton::L3,
+    JoypadButton::R1,
+    JoypadButton::R2,
+    JoypadButton::R3
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct JoypadState( u16 );
+
+impl JoypadState {
+    pub fn from_bits( bits: u16 ) -> JoypadState {
+        JoypadState( bits )
+    }
+
+    pub fn bits( self ) -> u16 {
+        self.0
+    }
+
+    pub fn is_pressed( self, button: JoypadButton ) -> bool {
+        self.0 & (1 << joypad_button_device_id( button )) != 0
+    }
+
+    pub fn is_empty( self ) -> bool {
+        self.0 == 0
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum AnalogStick {
+    Left,
+    Right
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct JoypadSnapshot {
+    pub buttons: JoypadState,
+    pub left_stick: (i16, i16),
+    pub right_stick: (i16, i16)
+}
+
+impl JoypadSnapshot {
+    pub fn is_pressed( &self, button: JoypadButton ) -> bool {
+        self.buttons.is_pressed( button )
+    }
+
+    pub fn analog_stick( &self, stick: AnalogStick ) -> (i16, i16) {
+        match stick {
+            AnalogStick::Left => self.left_stick,
+            AnalogStick::Right => self.right_stick
+        }
+    }
+}
+
+// Coordinates are normalized to the -0x7fff..0x7fff range,
+// where (0, 0) is the center of the screen.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PointerState {
+    pub x: i16,
+    pub y: i16,
+    pub is_pressed: bool
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    WheelUp,
+    WheelDown,
+    HorizontalWheelUp,
+    HorizontalWheelDown
+}
+
+#[repr(C)]
+struct KeyboardCallback {
+    callback: unsafe extern "C" fn( down: bool, keycode: libc::c_uint, character: u32, key_modifiers: u16 )
+}
+
+pub(crate) struct KeyboardEvent {
+    is_pressed: bool,
+    keycode: libc::c_uint,
+    character: u32,
+    modifiers: u16
+}
+
+// The frontend can call the keyboard callback at any point, so we
+// queue up the events and hand them over to the core right before
+// the next frame. They end up with the instance the frontend is
+// currently inside of (usually in `retro_run` while polling input),
+// or with the most recently initialized one otherwise.
+unsafe extern "C" fn on_keyboard_event( down: bool, keycode: libc::c_uint, character: u32, key_modifiers: u16 ) {
+    let event = KeyboardEvent {
+        is_pressed: down,
+        keycode: keycode,
+        character: character,
+        modifiers: key_modifiers
+    };
+
+    let _ = ::catch_panic( || {
+        interfaces::with( |interfaces| lock( &interfaces.keyboard_events ).push( event ) );
+    });
+}
+
+pub(crate) fn register_keyboard_callback() {
+    let keyboard_callback = KeyboardCallback {
+        callback: on_keyboard_event
+    };
+
+    unsafe {
+        let _ = call_environment( libretro_sys::ENVIRONMENT_SET_KEYBOARD_CALLBACK, &keyboard_callback );
+    }
+}
+
+fn joypad_button_device_id( button: JoypadButton ) -> libc::c_uint {
+    match button {
+        JoypadButton::A => libretro_sys::DEVICE_ID_JOYPAD_A,
+        JoypadButton::B => libretro_sys::DEVICE_ID_JOYPAD_B,
+        JoypadButton::X => libretro_sys::DEVICE_ID_JOYPAD_X,
+        JoypadButton::Y => libretro_sys::DEVICE_ID_JOYPAD_Y,
+        JoypadButton::Start => libretro_sys::DEVICE_ID_JOYPAD_START,
+        JoypadButton::Select => libretro_sys::DEVICE_ID_JOYPAD_SELECT,
+        JoypadButton::Left => libretro_sys::DEVICE_ID_JOYPAD_LEFT,
+        JoypadButton::Right => libretro_sys::DEVICE_ID_JOYPAD_RIGHT,
+        JoypadButton::Up => libretro_sys::DEVICE_ID_JOYPAD_UP,
+        JoypadButton::Down => libretro_sys::DEVICE_ID_JOYPAD_DOWN,
+        JoypadButton::L1 => libretro_sys::DEVICE_ID_JOYPAD_L,
+        JoypadButton::L2 => libretro_sys::DEVICE_ID_JOYPAD_L2,
+        JoypadButton::L3 => libretro_sys::DEVICE_ID_JOYPAD_L3,
+        JoypadButton::R1 => libretro_sys::DEVICE_ID_JOYPAD_R,
+        JoypadButton::R2 => libretro_sys::DEVICE_ID_JOYPAD_R2,
+        JoypadButton::R3 => libretro_sys::DEVICE_ID_JOYPAD_R3
+    }
+}
+
+fn analog_stick_index( stick: AnalogStick ) -> libc::c_uint {
+    match stick {
+        AnalogStick::Left => libretro_sys::DEVICE_INDEX_ANALOG_LEFT,
+        AnalogStick::Right => libretro_sys::DEVICE_INDEX_ANALOG_RIGHT
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PlayerInput {
+    port: u32,
+    joypad: JoypadSnapshot
+}
+
+impl PlayerInput {
+    pub fn port( &self ) -> u32 {
+        self.port
+    }
+
+    pub fn joypad( &self ) -> &JoypadSnapshot {
+        &self.joypad
+    }
+
+    pub fn is_pressed( &self, button: JoypadButton ) -> bool {
+        self.joypad.is_pressed( button )
+    }
+
+    pub fn analog_stick( &self, stick: AnalogStick ) -> (i16, i16) {
+        self.joypad.analog_stick( stick )
+    }
+}
+
+pub struct Players< 'a > {
+    handle: &'a mut RuntimeHandle,
+    port: u32
+}
+
+impl< 'a > Iterator for Players< 'a > {
+    type Item = PlayerInput;
+
+    fn next( &mut self ) -> Option< Self::Item > {
+        if self.port >= self.handle.max_users {
+            return None;
+        }
+
+        let port = self.port;
+        self.port += 1;
+
+        Some( PlayerInput {
+            port: port,
+            joypad: self.handle.poll_joypad( port )
+        })
+    }
+}
+
+impl RuntimeHandle {
+    pub fn players< 'a >( &'a mut self ) -> Players< 'a > {
+        Players {
+            handle: self,
+            port: 0
+        }
+    }
+
+    pub fn is_joypad_button_pressed( &mut self, port: u32, button: JoypadButton ) -> bool {
+        let device_id = joypad_button_device_id( button );
+        unsafe {
+            let value = (self.input_state_callback)( port, libretro_sys::DEVICE_JOYPAD, 0, device_id );
+            return value == 1;
+        }
+    }
+
+    pub fn joypad_state( &mut self, port: u32 ) -> JoypadState {
+        let index = port as usize;
+        if let Some( &Some( state ) ) = self.joypad_states.get( index ) {
+            return state;
+        }
+
+        let state = self.read_joypad_state( port );
+        if self.joypad_states.len() <= index {
+            self.joypad_states.resize( index + 1, None );
+        }
+
+        self.joypad_states[ index ] = Some( state );
+        state
+    }
+
+    pub fn previous_joypad_state( &self, port: u32 ) -> JoypadState {
+        self.previous_joypad_states.get( port as usize ).cloned().unwrap_or_default()
+    }
+
+    pub fn was_just_pressed( &mut self, port: u32, button: JoypadButton ) -> bool {
+        self.joypad_state( port ).is_pressed( button ) && self.previous_joypad_state( port ).is_pressed( button ) == false
+    }
+
+    pub fn was_just_released( &mut self, port: u32, button: JoypadButton ) -> bool {
+        self.joypad_state( port ).is_pressed( button ) == false && self.previous_joypad_state( port ).is_pressed( button )
+    }
+
+    // Every port that was looked at either this frame or the previous one
+    // gets its state recorded, so that the edges stay correct even if
+    // the core doesn't query a given port every single frame.
+    pub(crate) fn finish_joypad_states( &mut self ) -> Vec< JoypadState > {
+        let count = max( self.joypad_states.len(), self.previous_joypad_states.len() );
+        (0..count).map( |port| self.joypad_state( port as u32 ) ).collect()
+    }
+
+    fn read_joypad_state( &mut self, port: u32 ) -> JoypadState {
+        if self.supports_input_bitmasks {
+            let bits = unsafe {
+                (self.input_state_callback)( port, libretro_sys::DEVICE_JOYPAD, 0, DEVICE_ID_JOYPAD_MASK )
+            };
+
+            return JoypadState( bits as u16 );
+        }
+
+        let mut bits = 0;
+        for &button in JOYPAD_BUTTONS.iter() {
+            if self.is_joypad_button_pressed( port, button ) {
+                bits |= 1 << joypad_button_device_id( button );
+            }
+        }
+
+        JoypadState( bits )
+    }
+
+    pub fn poll_joypad( &mut self, port: u32 ) -> JoypadSnapshot {
+        JoypadSnapshot {
+            buttons: self.joypad_state( port ),
+            left_stick: self.analog_stick( port, AnalogStick::Left ),
+            right_stick: self.analog_stick( port, AnalogStick::Right )
+        }
+    }
+
+    pub fn joypad_button_pressure( &mut self, port: u32, button: JoypadButton ) -> i16 {
+        let device_id = joypad_button_device_id( button );
+        unsafe {
+            (self.input_state_callback)( port, libretro_sys::DEVICE_ANALOG, DEVICE_INDEX_ANALOG_BUTTON, device_id )
+        }
+    }
+
+    pub fn analog_stick( &mut self, port: u32, stick: AnalogStick ) -> (i16, i16) {
+        let index = analog_stick_index( stick );
+
+        unsafe {
+            let x = (self.input_state_callback)( port, libretro_sys::DEVICE_ANALOG, index, libretro_sys::DEVICE_ID_ANALOG_X );
+            let y = (self.input_state_callback)( port, libretro_sys::DEVICE_ANALOG, index, libretro_sys::DEVICE_ID_ANALOG_Y );
+            (x, y)
+        }
+    }
+
+    pub fn mouse_delta( &mut self, port: u32 ) -> (i16, i16) {
+        unsafe {
+            let x = (self.input_state_callback)( port, libretro_sys::DEVICE_MOUSE, 0, libretro_sys::DEVICE_ID_MOUSE_X );
+            let y = (self.input_state_callback)( port, libretro_sys::DEVICE_MOUSE, 0, libretro_sys::DEVICE_ID_MOUSE_Y );
+            (x, y)
+        }
+    }
+
+    pub fn is_mouse_button_pressed( &mut self, port: u32, button: MouseButton ) -> bool {
+        let device_id = match button {
+            MouseButton::Left => libretro_sys::DEVICE_ID_MOUSE_LEFT,
+            MouseButton::Right => libretro_sys::DEVICE_ID_MOUSE_RIGHT,
+            MouseButton::Middle => libretro_sys::DEVICE_ID_MOUSE_MIDDLE,
+            MouseButton::WheelUp => libretro_sys::DEVICE_ID_MOUSE_WHEELUP,
+            MouseButton::WheelDown => libretro_sys::DEVICE_ID_MOUSE_WHEELDOWN,
+            MouseButton::HorizontalWheelUp => libretro_sys::DEVICE_ID_MOUSE_HORIZ_WHEELUP,
+            MouseButton::HorizontalWheelDown => libretro_sys::DEVICE_ID_MOUSE_HORIZ_WHEELDOWN
+        };
+
+        unsafe {
+            let value = (self.input_state_callback)( port, libretro_sys::DEVICE_MOUSE, 0, device_id );
+            value != 0
+        }
+    }
+
+    pub fn pointer_count( &mut self, port: u32 ) -> u32 {
+        let count = unsafe {
+            (self.input_state_callback)( port, libretro_sys::DEVICE_POINTER, 0, DEVICE_ID_POINTER_COUNT )
+        };
+
+        // Older frontends don't support multi-touch, in which case
+        // there's always exactly one pointer available.
+        max( count, 1 ) as u32
+    }
+
+    pub fn pointer( &mut self, port: u32, index: u32 ) -> PointerState {
+        unsafe {
+            let x = (self.input_state_callback)( port, libretro_sys::DEVICE_POINTER, index, libretro_sys::DEVICE_ID_POINTER_X );
+            let y = (self.input_state_callback)( port, libretro_sys::DEVICE_POINTER, index, libretro_sys::DEVICE_ID_POINTER_Y );
+            let is_pressed = (self.input_state_callback)( port, libretro_sys::DEVICE_POINTER, index, libretro_sys::DEVICE_ID_POINTER_PRESSED ) != 0;
+            PointerState {
+                x: x,
+                y: y,
+                is_pressed: is_pressed
+            }
+        }
+    }
+
+    pub fn pointers( &mut self, port: u32 ) -> Vec< PointerState > {
+        let count = self.pointer_count( port );
+        (0..count).map( |index| self.pointer( port, index ) ).collect()
+    }
+
+    pub fn is_key_pressed( &mut self, key: Key ) -> bool {
+        unsafe {
+            let value = (self.input_state_callback)( 0, libretro_sys::DEVICE_KEYBOARD, 0, key.keycode() as libc::c_uint );
+            value != 0
+        }
+    }
+}
+
+impl< B: Core > Retro< B > {
+    pub(crate) fn dispatch_keyboard_events( &mut self ) {
+        let events = mem::replace( &mut *lock( &self.interfaces.keyboard_events ), Vec::new() );
+
+        for event in events {
+            let key = Key::from_keycode( event.keycode as u32 );
+            let character = if event.character == 0 {
+                None
+            } else {
+                std::char::from_u32( event.character )
+            };
+
+            self.core.on_keyboard_event( event.is_pressed, key, character, KeyModifiers::from_bits( event.modifiers ) );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::Cell;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+#[cfg(feature = "hw-render")]
+use hw_render;
+use input::KeyboardEvent;
+use lock;
+use logging::LogFn;
+use memory_map::MemoryMap;
+use perf::RawPerfInterface;
+#[cfg(feature = "vfs")]
+use vfs::Vfs;
+
+// Everything we get from (or hand over to) the frontend through the environment
+// callback; every `Retro` has its own, so that several instances living in
+// the same process don't end up stepping on each other's toes.
+pub(crate) struct Interfaces {
+    pub log: Mutex< Option< LogFn > >,
+    pub perf: Mutex< Option< RawPerfInterface > >,
+    #[cfg(feature = "vfs")]
+    pub vfs: Mutex< Option< Vfs > >,
+    #[cfg(feature = "hw-render")]
+    pub hw_render: Mutex< Option< hw_render::State > >,
+    pub memory_map: Mutex< Option< MemoryMap > >,
+    pub keyboard_events: Mutex< Vec< KeyboardEvent > >
+}
+
+impl Interfaces {
+    pub const fn new() -> Interfaces {
+        Interfaces {
+            log: Mutex::new( None ),
+            perf: Mutex::new( None ),
+            #[cfg(feature = "vfs")]
+            vfs: Mutex::new( None ),
+            #[cfg(feature = "hw-render")]
+            hw_render: Mutex::new( None ),
+            memory_map: Mutex::new( None ),
+            keyboard_events: Mutex::new( Vec::new() )
+        }
+    }
+
+    // A new instance starts out with whatever the frontend has given us
+    // in `retro_set_environment`, which is called before `retro_init`.
+    pub fn inherit_defaults() -> Interfaces {
+        let interfaces = Interfaces::new();
+        *lock( &interfaces.log ) = *lock( &DEFAULT_INTERFACES.log );
+        *lock( &interfaces.perf ) = *lock( &DEFAULT_INTERFACES.perf );
+        #[cfg(feature = "vfs")]
+        {
+            *lock( &interfaces.vfs ) = *lock( &DEFAULT_INTERFACES.vfs );
+        }
+
+        interfaces
+    }
+}
+
+// Makes sure nothing is left pointing at an instance which
+// went away without `retro_deinit` being called first.
+impl Drop for Interfaces {
+    fn drop( &mut self ) {
+        clear_active( self );
+    }
+}
+
+// Used when there isn't any instance yet, e.g. in `retro_set_environment`.
+static DEFAULT_INTERFACES: Interfaces = Interfaces::new();
+
+// The interfaces of the most recently initialized instance.
+static ACTIVE_INTERFACES: AtomicPtr< Interfaces > = AtomicPtr::new( 0 as *mut Interfaces );
+
+thread_local! {
+    // Set while inside of `Retro::enter`, and takes priority over the active ones.
+    static CURRENT_INTERFACES: Cell< *const Interfaces > = Cell::new( ptr::null() );
+}
+
+pub(crate) fn replace_current( interfaces: *const Interfaces ) -> *const Interfaces {
+    CURRENT_INTERFACES.with( |current| current.replace( interfaces ) )
+}
+
+// Whether this thread is currently inside of `Retro::enter`.
+pub(crate) fn is_entered() -> bool {
+    CURRENT_INTERFACES.with( |current| current.get().is_null() == false )
+}
+
+pub(crate) fn set_active( interfaces: *const Interfaces ) {
+    ACTIVE_INTERFACES.store( interfaces as *mut Interfaces, Ordering::Release );
+}
+
+pub(crate) fn clear_active( interfaces: *const Interfaces ) {
+    let _ = ACTIVE_INTERFACES.compare_exchange( interfaces as *mut Interfaces, ptr::null_mut(), Ordering::AcqRel, Ordering::Acquire );
+}
+
+pub(crate) fn with< R, F: FnOnce( &Interfaces ) -> R >( callback: F ) -> R {
+    let mut interfaces = CURRENT_INTERFACES.with( |current| current.get() );
+    if interfaces.is_null() {
+        interfaces = ACTIVE_INTERFACES.load( Ordering::Acquire );
+    }
+
+    if interfaces.is_null() {
+        callback( &DEFAULT_INTERFACES )
+    } else {
+        // Both pointers are cleared before the instance they belong to goes away.
+        callback( unsafe { &*interfaces } )
+    }
+}
@@ -0,0 +1,57 @@
+macro_rules! languages {
+    ($($name: ident = $value: tt),+) => (
+        #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+        pub enum Language {
+            $($name = $value),+
+        }
+
+        impl Language {
+            pub fn from_raw( value: u32 ) -> Option< Language > {
+                match value {
+                    $($value => Some( Language::$name ),)+
+                    _ => None
+                }
+            }
+
+            pub fn raw( self ) -> u32 {
+                self as u32
+            }
+        }
+    )
+}
+
+languages! {
+    English = 0,
+    Japanese = 1,
+    French = 2,
+    Spanish = 3,
+    German = 4,
+    Italian = 5,
+    Dutch = 6,
+    PortugueseBrazil = 7,
+    PortuguesePortugal = 8,
+    Russian = 9,
+    Korean = 10,
+    ChineseTraditional = 11,
+    ChineseSimplified = 12,
+    Esperanto = 13,
+    Polish = 14,
+    Vietnamese = 15,
+    Arabic = 16,
+    Greek = 17,
+    Turkish = 18,
+    Slovak = 19,
+    Persian = 20,
+    Hebrew = 21,
+    Asturian = 22,
+    Finnish = 23,
+    Indonesian = 24,
+    Swedish = 25,
+    Ukrainian = 26,
+    Czech = 27,
+    CatalanValencia = 28,
+    Catalan = 29,
+    BritishEnglish = 30,
+    Hungarian = 31,
+    Belarusian = 32
+}
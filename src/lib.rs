@@ -3,20 +3,136 @@ pub extern crate libc;
 #[doc(hidden)]
 pub extern crate libretro_sys;
 
+#[cfg(feature = "derive")]
+extern crate libretro_backend_derive;
+#[cfg(feature = "glow")]
+pub extern crate glow;
+#[cfg(feature = "log")]
+extern crate log as log_crate;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+
 use std::mem;
 use std::ptr;
 use std::slice;
+use std::panic;
 use std::ffi::{CStr, CString};
-use std::cmp::max;
+use std::cmp::{min, max};
+use std::sync::{Mutex, MutexGuard};
+use std::cell::Cell;
+use std::any::TypeId;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::error;
+use std::fmt;
 
 pub use libretro_sys::{PixelFormat, Region};
 
+use environment::{call_environment, call_environment_mut, call_environment_raw};
+use video::negotiate_pixel_format;
+use audio::{SILENT_AUDIO_SAMPLE_RATE, AUDIO_CONVERSION_CHUNK_SIZE};
+use determinism::DeterminismChecker;
+#[cfg(feature = "cheats")]
+use cheats::CheatEngine;
+use content_override::ContentOverrides;
+use interfaces::Interfaces;
+
+#[cfg(feature = "derive")]
+pub use libretro_backend_derive::CoreOptions;
+
+// Can't live at the crate root since it'd clash with the `libretro_core!` macro.
+#[cfg(feature = "derive")]
+pub mod attributes {
+    pub use libretro_backend_derive::libretro_core;
+}
+
+pub mod environment;
+mod video;
+mod audio;
+mod input;
+mod macros;
+mod context;
+mod interfaces;
+#[cfg(feature = "options")]
+pub mod options;
+mod keyboard;
+mod language;
+mod sensor;
+mod midi;
+mod audio_queue;
+mod disk_control;
+mod subsystem;
+mod content_override;
+mod memory_map;
+mod netpacket;
+mod frame;
+mod determinism;
+mod dynamic;
+mod message;
+pub mod logging;
+pub mod perf;
+pub mod localization;
+#[cfg(feature = "vfs")]
+pub mod vfs;
+pub mod filesystem;
+pub mod m3u;
+#[cfg(feature = "cheats")]
+pub mod cheats;
+pub mod scaler;
+#[cfg(feature = "debug-text")]
+pub mod debug_text;
+#[cfg(feature = "hw-render")]
+pub mod hw_render;
+#[cfg(feature = "resampler")]
+pub mod resampler;
+
+pub use environment::{EnvError, FrontendInstant, SavestateContext, FastForwarding};
+pub use context::FrontendContext;
+pub use video::FrontendFrameBuffer;
+pub use audio::AudioPacing;
+pub use input::{DeviceType, ControllerPort, ControllerInfo, InputDescriptors, JoypadButton, JoypadState, JoypadSnapshot, AnalogStick, PointerState, MouseButton, PlayerInput, Players};
+#[doc(hidden)]
+pub use macros::{construct, create, catch_panic, Instance, request_shutdown};
+#[cfg(feature = "options")]
+pub use options::{CoreOption, CoreOptions};
+pub use keyboard::{Key, KeyModifiers};
+pub use language::Language;
+pub use sensor::{Sensor, Sensors};
+pub use midi::Midi;
+pub use audio_queue::AudioQueue;
+pub use disk_control::DiskControl;
+pub use subsystem::{Subsystem, SubsystemRom, Subsystems};
+pub use content_override::ContentOverride;
+pub use memory_map::{MemoryDescriptor, MemoryKind, MemoryMap};
+pub use frame::{Frame, FrameBuffers, Surface, SurfaceMut, Rect, fill_rect, blit, Pixel, Rgb565, Argb1555, Argb8888, dither_rgb565, dither_frame};
+pub use netpacket::{Netpacket, PacketFlags, HOST_CLIENT_ID, BROADCAST_CLIENT_ID};
+pub use message::{Message, LogLevel, MessageTarget, MessageKind};
+#[cfg(feature = "hw-render")]
+pub use hw_render::HardwareContext;
+pub use dynamic::{CoreDyn, CoreFactory, DynamicCore, CoreChoice, CoreRegistry, MultiCore};
+
 pub struct CoreInfo {
     library_name: CString,
     library_version: CString,
     supported_romfile_extensions: CString,
     require_path_when_loading_roms: bool,
-    allow_frontend_to_extract_archives: bool
+    allow_frontend_to_extract_archives: bool,
+    #[cfg(feature = "options")]
+    options: Vec< CoreOption >,
+    input_descriptors: InputDescriptors,
+    controller_info: ControllerInfo,
+    performance_level: Option< u32 >,
+    supports_disk_control: bool,
+    subsystems: Subsystems,
+    netpacket_protocol_version: Option< CString >,
+    check_determinism: bool,
+    #[cfg(feature = "cheats")]
+    has_builtin_cheats: bool,
+    supports_running_without_game: bool,
+    content_overrides: ContentOverrides
 }
 
 impl CoreInfo {
@@ -26,7 +142,20 @@ impl CoreInfo {
             library_version: CString::new( version ).unwrap(),
             supported_romfile_extensions: CString::new( "" ).unwrap(),
             require_path_when_loading_roms: false,
-            allow_frontend_to_extract_archives: true
+            allow_frontend_to_extract_archives: true,
+            #[cfg(feature = "options")]
+            options: Vec::new(),
+            input_descriptors: InputDescriptors::new(),
+            controller_info: ControllerInfo::new(),
+            performance_level: None,
+            supports_disk_control: false,
+            subsystems: Subsystems::new(),
+            netpacket_protocol_version: None,
+            check_determinism: false,
+            #[cfg(feature = "cheats")]
+            has_builtin_cheats: false,
+            supports_running_without_game: false,
+            content_overrides: ContentOverrides::new()
         }
     }
 
@@ -62,6 +191,80 @@ impl CoreInfo {
         self.require_path_when_loading_roms = true;
         self
     }
+
+    pub fn content_override( mut self, content_override: ContentOverride ) -> Self {
+        self.content_overrides.push( content_override );
+        self
+    }
+
+    // `Core::on_load_game` will then get an empty `GameData`
+    // when the frontend starts the core without any content.
+    pub fn supports_running_without_game( mut self ) -> Self {
+        self.supports_running_without_game = true;
+        self
+    }
+
+    #[cfg(feature = "options")]
+    pub fn option( mut self, option: CoreOption ) -> Self {
+        self.options.push( option );
+        self
+    }
+
+    #[cfg(feature = "options")]
+    pub fn options< T: CoreOptions >( mut self ) -> Self {
+        self.options.extend( T::options() );
+        self
+    }
+
+    pub fn input_descriptors( mut self, descriptors: InputDescriptors ) -> Self {
+        self.input_descriptors = descriptors;
+        self
+    }
+
+    pub fn controller_info( mut self, controller_info: ControllerInfo ) -> Self {
+        self.controller_info = controller_info;
+        self
+    }
+
+    pub fn subsystems( mut self, subsystems: Subsystems ) -> Self {
+        self.subsystems = subsystems;
+        self
+    }
+
+    // The core also has to return something from `Core::disk_control`.
+    pub fn supports_disk_control( mut self ) -> Self {
+        self.supports_disk_control = true;
+        self
+    }
+
+    // Enables the `Core::on_netpacket_*` hooks; only peers
+    // with the same protocol version can connect to each other.
+    pub fn netpacket( mut self, protocol_version: &str ) -> Self {
+        self.netpacket_protocol_version = Some( CString::new( protocol_version ).unwrap() );
+        self
+    }
+
+    // Applies the frontend's cheats to `Core::system_memory` before every
+    // frame using `cheats::CheatEngine`; codes are in the `address:value` format.
+    #[cfg(feature = "cheats")]
+    pub fn builtin_cheats( mut self ) -> Self {
+        self.has_builtin_cheats = true;
+        self
+    }
+
+    // Round-trips a savestate after every frame and logs the first time
+    // it doesn't match; this is slow, so it's only meant for debugging.
+    pub fn check_determinism( mut self ) -> Self {
+        self.check_determinism = true;
+        self
+    }
+
+    // A rough, relative hint of how demanding the core is;
+    // see the libretro documentation for typical values.
+    pub fn performance_level( mut self, level: u32 ) -> Self {
+        self.performance_level = Some( level );
+        self
+    }
 }
 
 pub struct AudioVideoInfo {
@@ -73,7 +276,10 @@ pub struct AudioVideoInfo {
     audio_sample_rate: f64,
     aspect_ratio: Option< f32 >,
     pixel_format: PixelFormat,
-    game_region: Option< Region >
+    game_region: Option< Region >,
+    audio_pacing: AudioPacing,
+    #[cfg(feature = "hw-render")]
+    hardware_context: Option< HardwareContext >
 }
 
 impl AudioVideoInfo {
@@ -87,7 +293,10 @@ impl AudioVideoInfo {
             aspect_ratio: None,
             pixel_format: PixelFormat::RGB565,
             audio_sample_rate: 0.0,
-            game_region: None
+            game_region: None,
+            audio_pacing: AudioPacing::Strict,
+            #[cfg(feature = "hw-render")]
+            hardware_context: None
         }
     }
 
@@ -117,11 +326,65 @@ impl AudioVideoInfo {
         self
     }
 
+    // The frontend still gets a stream of silence, since some
+    // of them use the audio to pace the emulation.
+    pub fn no_audio( mut self ) -> Self {
+        self.audio_sample_rate = 0.0;
+        self
+    }
+
+    pub fn audio_pacing( mut self, audio_pacing: AudioPacing ) -> Self {
+        self.audio_pacing = audio_pacing;
+        self
+    }
+
     pub fn region( mut self, game_region: Region ) -> Self {
         self.game_region = Some( game_region );
         self
     }
 
+    #[cfg(feature = "hw-render")]
+    pub fn hardware_context( mut self, context: HardwareContext ) -> Self {
+        self.hardware_context = Some( context );
+        self
+    }
+
+    fn to_raw( &self ) -> libretro_sys::SystemAvInfo {
+        libretro_sys::SystemAvInfo {
+            geometry: libretro_sys::GameGeometry {
+                base_width: self.width as libc::c_uint,
+                base_height: self.height as libc::c_uint,
+                max_width: self.max_width as libc::c_uint,
+                max_height: self.max_height as libc::c_uint,
+                aspect_ratio: self.aspect_ratio.unwrap_or( 0.0 )
+            },
+            timing: libretro_sys::SystemTiming {
+                fps: self.frames_per_second,
+                sample_rate: self.effective_audio_sample_rate()
+            }
+        }
+    }
+
+    fn has_audio( &self ) -> bool {
+        self.audio_sample_rate > 0.0
+    }
+
+    fn effective_audio_sample_rate( &self ) -> f64 {
+        if self.has_audio() {
+            self.audio_sample_rate
+        } else {
+            SILENT_AUDIO_SAMPLE_RATE
+        }
+    }
+
+    fn effective_audio_pacing( &self ) -> AudioPacing {
+        if self.has_audio() {
+            self.audio_pacing
+        } else {
+            AudioPacing::PadWithSilence
+        }
+    }
+
     fn infer_game_region( &self ) -> Region {
         self.game_region.unwrap_or_else( || {
             if self.frames_per_second > 59.0 {
@@ -141,10 +404,69 @@ pub struct GameData {
     // and he has to give us a GameData object back in on_unload_game,
     // and since we're the only source of those he has to give us
     // the one that he got in on_load_game.
-    data: Option< &'static [u8] >
+    data: Option< &'static [u8] >,
+
+    // Only available when the frontend supports `GET_GAME_INFO_EXT`.
+    full_path: Option< String >,
+    archive_path: Option< String >,
+    archive_file: Option< String >,
+    directory: Option< String >,
+    base_name: Option< String >,
+    extension: Option< String >,
+    is_persistent: bool
+}
+
+unsafe fn string_from_ptr( pointer: *const libc::c_char ) -> Option< String > {
+    if pointer == ptr::null() {
+        return None;
+    }
+
+    CStr::from_ptr( pointer ).to_str().ok().map( |string| string.to_owned() )
 }
 
 impl GameData {
+    fn empty() -> GameData {
+        GameData {
+            path: None,
+            data: None,
+            full_path: None,
+            archive_path: None,
+            archive_file: None,
+            directory: None,
+            base_name: None,
+            extension: None,
+            is_persistent: false
+        }
+    }
+
+    unsafe fn from_raw( game_info: *const libretro_sys::GameInfo, game_info_ext: *const RawGameInfoExt ) -> GameData {
+        let mut game_data = GameData::empty();
+        if game_info == ptr::null() {
+            return game_data;
+        }
+
+        let game_info = &*game_info;
+        game_data.path = string_from_ptr( game_info.path );
+        game_data.data = if game_info.data == ptr::null() && game_info.size == 0 {
+            None
+        } else {
+            Some( slice::from_raw_parts( game_info.data as *const u8, game_info.size ) )
+        };
+
+        if game_info_ext != ptr::null() {
+            let game_info_ext = &*game_info_ext;
+            game_data.full_path = string_from_ptr( game_info_ext.full_path );
+            game_data.archive_path = string_from_ptr( game_info_ext.archive_path );
+            game_data.archive_file = string_from_ptr( game_info_ext.archive_file );
+            game_data.directory = string_from_ptr( game_info_ext.dir );
+            game_data.base_name = string_from_ptr( game_info_ext.name );
+            game_data.extension = string_from_ptr( game_info_ext.ext );
+            game_data.is_persistent = game_info_ext.persistent_data;
+        }
+
+        game_data
+    }
+
     pub fn path( &self ) -> Option< &str > {
         self.path.as_ref().map( |path| &path[..] )
     }
@@ -156,37 +478,144 @@ impl GameData {
     pub fn is_empty( &self ) -> bool {
         self.path().is_none() && self.data().is_none()
     }
+
+    // The path of the content itself, even if it's inside of an archive
+    // (in which case it's `archive_path#archive_file`) or was loaded from memory.
+    pub fn full_path( &self ) -> Option< &str > {
+        self.full_path.as_ref().map( |path| &path[..] )
+    }
+
+    pub fn archive_path( &self ) -> Option< &str > {
+        self.archive_path.as_ref().map( |path| &path[..] )
+    }
+
+    // The path of the content inside of the archive.
+    pub fn archive_file( &self ) -> Option< &str > {
+        self.archive_file.as_ref().map( |path| &path[..] )
+    }
+
+    // The directory the content (or its archive) is in.
+    pub fn directory( &self ) -> Option< &str > {
+        self.directory.as_ref().map( |path| &path[..] )
+    }
+
+    // The file name of the content without its extension; meant for per-game files.
+    pub fn base_name( &self ) -> Option< &str > {
+        self.base_name.as_ref().map( |name| &name[..] )
+    }
+
+    // Always lowercase.
+    pub fn extension( &self ) -> Option< &str > {
+        self.extension.as_ref().map( |extension| &extension[..] )
+    }
+
+    // Whether the frontend keeps `data` around until the game is unloaded.
+    pub fn is_persistent( &self ) -> bool {
+        self.is_persistent
+    }
+
+    // Same as `data`, but only if the frontend has promised to keep it
+    // around until the game is unloaded; see `ContentOverride::persistent_data`.
+    pub fn persistent_data( &self ) -> Option< &'static [u8] > {
+        if self.is_persistent == false {
+            return None;
+        }
+
+        self.data
+    }
+}
+
+// The game data has to be handed back when the game couldn't be loaded,
+// just as it has to be in `Core::on_unload_game`.
+pub struct LoadGameError< E > {
+    game_data: GameData,
+    error: Option< E >
+}
+
+impl< E > LoadGameError< E > {
+    pub fn new( game_data: GameData, error: E ) -> Self {
+        LoadGameError {
+            game_data: game_data,
+            error: Some( error )
+        }
+    }
+
+    // For content the core doesn't support; nothing gets logged.
+    pub fn unsupported( game_data: GameData ) -> Self {
+        LoadGameError {
+            game_data: game_data,
+            error: None
+        }
+    }
+
+    pub fn error( &self ) -> Option< &E > {
+        self.error.as_ref()
+    }
+
+    pub fn map_error< F, C: FnOnce( E ) -> F >( self, callback: C ) -> LoadGameError< F > {
+        LoadGameError {
+            game_data: self.game_data,
+            error: self.error.map( callback )
+        }
+    }
 }
 
-pub enum LoadGameResult {
-    Success( AudioVideoInfo ),
-    Failed( GameData )
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum UploadError {
+    // The frame is bigger than the maximum size from `AudioVideoInfo`.
+    FrameTooLarge,
+    // The pitch is smaller than a single line of pixels.
+    PitchTooSmall,
+    // The data doesn't cover the whole frame.
+    DataTooSmall,
+    // The pixels aren't in the format from `AudioVideoInfo`.
+    PixelFormatMismatch,
+    // Audio data has to consist of interleaved left and right samples.
+    NotStereo,
+    // Only one frame can be uploaded per call to `Core::on_run`; the frontend
+    // shows the first one, so any overlays have to be drawn before uploading.
+    AlreadyUploaded,
+    // The frontend stopped accepting audio; that many samples were dropped.
+    FrontendStalled { dropped_samples: usize }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub enum JoypadButton {
-    A,
-    B,
-    X,
-    Y,
-    Select,
-    Start,
-    Up,
-    Down,
-    Left,
-    Right,
-    L1,
-    L2,
-    L3,
-    R1,
-    R2,
-    R3
+impl fmt::Display for UploadError {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        let message = match *self {
+            UploadError::FrameTooLarge => "the frame is bigger than the maximum video size",
+            UploadError::PitchTooSmall => "the pitch is smaller than a single line",
+            UploadError::DataTooSmall => "the data is too small to upload",
+            UploadError::PixelFormatMismatch => "the pixel format doesn't match the current one",
+            UploadError::NotStereo => "the audio data is not in stereo",
+            UploadError::AlreadyUploaded => "a video frame was already uploaded this frame",
+            UploadError::FrontendStalled { dropped_samples } => {
+                return write!( formatter, "the frontend stopped accepting audio; {} samples were dropped", dropped_samples );
+            }
+        };
+
+        formatter.write_str( message )
+    }
 }
 
-pub trait Core: Default {
+impl error::Error for UploadError {}
+
+pub trait Core: 'static {
+    // Errors get logged through the frontend, and the frontend
+    // is told that the call has failed.
+    type Error: fmt::Display;
+
     fn info() -> CoreInfo;
-    fn on_load_game( &mut self, game_data: GameData ) -> LoadGameResult;
+    // Called in `retro_init`; returning `None` falls back to the constructor
+    // given to `libretro_core!`, which is `Default::default()` by default.
+    fn create( _context: &FrontendContext ) -> Option< Self > where Self: Sized {
+        None
+    }
+    fn on_load_game( &mut self, game_data: GameData ) -> Result< AudioVideoInfo, LoadGameError< Self::Error > >;
     fn on_unload_game( &mut self ) -> GameData;
+    // Called instead of `on_load_game` for the subsystems declared in `CoreInfo`.
+    fn on_load_game_special( &mut self, _subsystem_id: u32, games: Vec< GameData > ) -> Result< AudioVideoInfo, LoadGameError< Self::Error > > {
+        Err( LoadGameError::unsupported( games.into_iter().next().unwrap_or_else( GameData::empty ) ) )
+    }
     fn on_run( &mut self, handle: &mut RuntimeHandle );
     fn on_reset( &mut self );
     fn save_memory( &mut self ) -> Option< &mut [u8] > {
@@ -201,9 +630,101 @@ pub trait Core: Default {
     fn video_memory( &mut self ) -> Option< &mut [u8] > {
         None
     }
+    fn serialize_size( &self, _context: SavestateContext ) -> usize {
+        0
+    }
+    // Only called when `serialize_size` isn't zero.
+    fn serialize( &self, _buffer: &mut [u8], _context: SavestateContext ) -> Result< (), Self::Error > {
+        Ok(())
+    }
+    fn unserialize( &mut self, _buffer: &[u8], _context: SavestateContext ) -> Result< (), Self::Error > {
+        Ok(())
+    }
+    fn on_keyboard_event( &mut self, _is_pressed: bool, _key: Option< Key >, _character: Option< char >, _modifiers: KeyModifiers ) {
+    }
+    fn on_set_controller_port_device( &mut self, _port: u32, _device: u32 ) {
+    }
+    fn on_hw_context_reset( &mut self ) {
+    }
+    fn on_hw_context_destroyed( &mut self ) {
+    }
+    #[cfg(feature = "glow")]
+    fn on_glow_context_reset( &mut self, _context: glow::Context ) {
+    }
+    fn disk_control( &mut self ) -> Option< &mut dyn DiskControl > {
+        None
+    }
+    // Called before a frame with the keys of the options the user has changed.
+    fn on_options_changed( &mut self, _changed_keys: &[&str] ) {
+    }
+    // Should call `options::set_visible` for any options whose visibility
+    // depends on other options; returns whether anything has changed.
+    fn on_update_option_visibility( &mut self ) -> bool {
+        false
+    }
+    fn on_cheat_reset( &mut self ) {
+    }
+    fn on_cheat_set( &mut self, _index: u32, _is_enabled: bool, _code: &str ) -> Result< (), Self::Error > {
+        Ok(())
+    }
+    fn on_netpacket_start( &mut self, _netpacket: Netpacket ) {
+    }
+    // Packets fetched with `Netpacket::poll_receive` go to its callback instead.
+    fn on_netpacket_receive( &mut self, _data: &[u8], _client_id: u16 ) {
+    }
+    fn on_netpacket_stop( &mut self ) {
+    }
+    // Called before each frame, so that packets can be sent or polled.
+    fn on_netpacket_poll( &mut self ) {
+    }
+    // Only called on the host; returning `false` rejects the client.
+    fn on_netpacket_connected( &mut self, _client_id: u16 ) -> bool {
+        true
+    }
+    fn on_netpacket_disconnected( &mut self, _client_id: u16 ) {
+    }
+}
+
+// Not exposed by libretro-sys yet.
+const ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE: libc::c_uint = 47 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+const ENVIRONMENT_GET_GAME_INFO_EXT: libc::c_uint = 66;
+const AUDIO_VIDEO_ENABLE_VIDEO: libc::c_int = 1 << 0;
+const AUDIO_VIDEO_ENABLE_AUDIO: libc::c_int = 1 << 1;
+const AUDIO_VIDEO_ENABLE_FAST_SAVESTATES: libc::c_int = 1 << 2;
+const AUDIO_VIDEO_ENABLE_HARD_DISABLE_AUDIO: libc::c_int = 1 << 3;
+
+#[repr(C)]
+struct RawGameInfoExt {
+    full_path: *const libc::c_char,
+    archive_path: *const libc::c_char,
+    archive_file: *const libc::c_char,
+    dir: *const libc::c_char,
+    name: *const libc::c_char,
+    ext: *const libc::c_char,
+    meta: *const libc::c_char,
+    data: *const libc::c_void,
+    size: libc::size_t,
+    file_in_archive: bool,
+    persistent_data: bool
 }
 
-static mut ENVIRONMENT_CALLBACK: Option< libretro_sys::EnvironmentFn > = None;
+// A panic caught at one of the entry points shouldn't leave
+// whatever it was holding locked away for good.
+fn lock< 'a, T >( mutex: &'a Mutex< T > ) -> MutexGuard< 'a, T > {
+    mutex.lock().unwrap_or_else( |error| error.into_inner() )
+}
+
+// Returns one entry per loaded content; only valid while the game is being loaded.
+fn query_game_info_ext() -> *const RawGameInfoExt {
+    let mut pointer: *const RawGameInfoExt = ptr::null();
+    unsafe {
+        if call_environment_mut( ENVIRONMENT_GET_GAME_INFO_EXT, &mut pointer ).is_err() {
+            return ptr::null();
+        }
+    }
+
+    pointer
+}
 
 #[doc(hidden)]
 pub struct Retro< B: Core > {
@@ -217,17 +738,56 @@ pub struct Retro< B: Core > {
 
     is_game_loaded: bool,
     av_info: AudioVideoInfo,
-    total_audio_samples_uploaded: usize
+    total_audio_samples_uploaded: usize,
+    supports_input_bitmasks: bool,
+    can_dupe: bool,
+    max_users: u32,
+    sensors: Option< Sensors >,
+    midi: Option< Midi >,
+    previous_joypad_states: Vec< JoypadState >,
+    fallback_frame_buffer: Vec< u8 >,
+    output_pixel_format: PixelFormat,
+    conversion_buffer: Vec< u8 >,
+    determinism_checker: DeterminismChecker,
+    #[cfg(feature = "cheats")]
+    cheats: CheatEngine,
+    #[cfg(feature = "options")]
+    option_values: Vec< Option< String > >,
+    // Overrides the global environment callback; see `set_environment_callback`.
+    environment_callback: Option< libretro_sys::EnvironmentFn >,
+    // Boxed since it has to stay put when the instance is moved.
+    interfaces: Box< Interfaces >
+}
+
+// The instance the frontend's callbacks get routed to when they come in from outside
+// of `Retro::enter`; that's the most recently initialized one.
+static ACTIVE_INSTANCE: AtomicPtr< libc::c_void > = AtomicPtr::new( 0 as *mut libc::c_void );
+
+thread_local! {
+    static CURRENT_INSTANCE: Cell< *mut libc::c_void > = Cell::new( ptr::null_mut() );
+}
+
+// Restores the previously entered instance even if the core panics.
+struct InstanceScope {
+    previous_instance: *mut libc::c_void,
+    previous_environment_callback: Option< libretro_sys::EnvironmentFn >,
+    previous_interfaces: *const Interfaces
+}
+
+impl Drop for InstanceScope {
+    fn drop( &mut self ) {
+        CURRENT_INSTANCE.with( |current| current.set( self.previous_instance ) );
+        environment::replace_current_environment_callback( self.previous_environment_callback );
+        interfaces::replace_current( self.previous_interfaces );
+    }
 }
 
 macro_rules! set_callback {
     ($output: expr, $input: expr) => (
-        unsafe {
-            if $input == mem::transmute( 0 as usize ) {
-                $output = None;
-            } else {
-                $output = Some( $input );
-            }
+        if $input as usize == 0 {
+            $output = None;
+        } else {
+            $output = Some( $input );
         }
     )
 }
@@ -245,34 +805,101 @@ impl< B: Core > Retro< B > {
 
             is_game_loaded: false,
             av_info: AudioVideoInfo::new(),
-            total_audio_samples_uploaded: 0
+            total_audio_samples_uploaded: 0,
+            supports_input_bitmasks: false,
+            can_dupe: false,
+            max_users: 1,
+            sensors: None,
+            midi: None,
+            previous_joypad_states: Vec::new(),
+            fallback_frame_buffer: Vec::new(),
+            output_pixel_format: PixelFormat::ARGB1555,
+            conversion_buffer: Vec::new(),
+            determinism_checker: DeterminismChecker::default(),
+            #[cfg(feature = "cheats")]
+            cheats: CheatEngine::new(),
+            #[cfg(feature = "options")]
+            option_values: Vec::new(),
+            environment_callback: None,
+            interfaces: Box::new( Interfaces::inherit_defaults() )
         }
     }
 
-    #[must_use]
-    unsafe fn call_environment< T >( &mut self, command: libc::c_uint, pointer: &T ) -> Result< (), () > {
-        let ok = ENVIRONMENT_CALLBACK.unwrap()( command, mem::transmute( pointer ) );
-        if ok {
-            Ok(())
+    // Makes every environment call and every frontend callback without any
+    // user data go to this instance until `callback` returns; this is what
+    // allows several instances to live side by side in the same process.
+    pub fn enter< R, F: FnOnce( &mut Self ) -> R >( &mut self, callback: F ) -> R {
+        let instance = self as *mut Self as *mut libc::c_void;
+        let _scope = InstanceScope {
+            previous_instance: CURRENT_INSTANCE.with( |current| current.replace( instance ) ),
+            previous_environment_callback: environment::replace_current_environment_callback( self.environment_callback ),
+            previous_interfaces: interfaces::replace_current( &*self.interfaces )
+        };
+
+        callback( self )
+    }
+
+    // The instance the frontend's callbacks without any user data get routed to.
+    pub(crate) unsafe fn active< 'a >() -> Option< &'a mut Self > {
+        let mut instance = CURRENT_INSTANCE.with( |current| current.get() ) as *mut Self;
+        if instance.is_null() {
+            instance = ACTIVE_INSTANCE.load( Ordering::Acquire ) as *mut Self;
+        }
+
+        if instance.is_null() {
+            None
         } else {
-            Err(())
+            Some( &mut *instance )
         }
     }
 
-    pub fn on_get_system_info( info: *mut libretro_sys::SystemInfo ) {
-        assert_ne!( info, ptr::null_mut() );
-        let info = unsafe { &mut *info };
+    // Called once the instance has its final address.
+    pub fn on_init( &mut self ) {
+        ACTIVE_INSTANCE.store( self as *mut Self as *mut libc::c_void, Ordering::Release );
+        interfaces::set_active( &*self.interfaces );
+    }
+
+    pub fn on_deinit( &mut self ) {
+        let _ = ACTIVE_INSTANCE.compare_exchange( self as *mut Self as *mut libc::c_void, ptr::null_mut(), Ordering::AcqRel, Ordering::Acquire );
+        interfaces::clear_active( &*self.interfaces );
+    }
 
+    fn core_info() -> &'static CoreInfo {
         // Pointers in SystemInfo have to be statically allocated,
-        // which is why we do this.
-        static mut INFO: Option< *const CoreInfo > = None;
-        let core_info = unsafe {
-            if INFO.is_none() {
-                INFO = Some( Box::into_raw( Box::new( B::info() ) ) );
+        // which is why we do this. A `static` inside of a generic function
+        // is shared between every `B`, hence the lookup by type.
+        static INFO: Mutex< Vec< (TypeId, usize) > > = Mutex::new( Vec::new() );
+        let lookup = || {
+            let infos = INFO.lock().unwrap();
+            infos.iter().find( |&&(type_id, _)| type_id == TypeId::of::< B >() ).map( |&(_, core_info)| core_info )
+        };
+
+        let core_info = match lookup() {
+            Some( core_info ) => core_info,
+            None => {
+                let new_core_info = Box::into_raw( Box::new( B::info() ) ) as usize;
+                let mut infos = INFO.lock().unwrap();
+                match infos.iter().find( |&&(type_id, _)| type_id == TypeId::of::< B >() ) {
+                    Some( &(_, existing) ) => {
+                        unsafe { mem::drop( Box::from_raw( new_core_info as *mut CoreInfo ) ); }
+                        existing
+                    },
+                    None => {
+                        infos.push( (TypeId::of::< B >(), new_core_info) );
+                        new_core_info
+                    }
+                }
             }
-            INFO.map( |core_info| &*core_info ).unwrap()
         };
 
+        unsafe { &*(core_info as *const CoreInfo) }
+    }
+
+    pub unsafe fn on_get_system_info( info: *mut libretro_sys::SystemInfo ) {
+        assert_ne!( info, ptr::null_mut() );
+        let info = unsafe { &mut *info };
+        let core_info = Self::core_info();
+
         info.library_name = core_info.library_name.as_ptr();
         info.library_version = core_info.library_version.as_ptr();
         info.valid_extensions = core_info.supported_romfile_extensions.as_ptr();
@@ -281,7 +908,52 @@ impl< B: Core > Retro< B > {
     }
 
     pub fn on_set_environment( callback: libretro_sys::EnvironmentFn ) {
-        set_callback!( ENVIRONMENT_CALLBACK, callback );
+        let environment_callback;
+        set_callback!( environment_callback, callback );
+        environment::set_environment_callback( environment_callback );
+        Self::register_with_frontend();
+    }
+
+    // Gives only this instance its own environment callback instead of
+    // the one passed to `retro_set_environment`.
+    pub fn set_environment_callback( &mut self, callback: libretro_sys::EnvironmentFn ) {
+        set_callback!( self.environment_callback, callback );
+        self.enter( |_| Self::register_with_frontend() );
+    }
+
+    fn register_with_frontend() {
+        logging::query();
+        perf::query();
+        #[cfg(feature = "vfs")]
+        vfs::query();
+
+        let core_info = Self::core_info();
+        #[cfg(feature = "options")]
+        {
+            if core_info.options.is_empty() == false {
+                options::register( &core_info.options );
+                options::register_update_display_callback::< B >();
+            }
+        }
+
+        if core_info.supports_running_without_game {
+            unsafe {
+                let _ = call_environment( libretro_sys::ENVIRONMENT_SET_SUPPORT_NO_GAME, &true );
+            }
+        }
+
+        core_info.controller_info.register();
+        core_info.subsystems.register();
+        core_info.content_overrides.register();
+        if core_info.supports_disk_control {
+            disk_control::register::< B >();
+        }
+
+        if let Some( ref protocol_version ) = core_info.netpacket_protocol_version {
+            netpacket::register::< B >( protocol_version.as_ptr() );
+        }
+
+        input::register_keyboard_callback();
     }
 
     pub fn on_set_video_refresh( &mut self, callback: libretro_sys::VideoRefreshFn ) {
@@ -304,96 +976,162 @@ impl< B: Core > Retro< B > {
         set_callback!( self.input_state_callback, callback );
     }
 
-    pub fn on_get_system_av_info( &mut self, info: *mut libretro_sys::SystemAvInfo ) {
+    pub unsafe fn on_get_system_av_info( &mut self, info: *mut libretro_sys::SystemAvInfo ) {
         assert_ne!( info, ptr::null_mut() );
         let info = unsafe { &mut *info };
 
-        info.geometry.base_width = self.av_info.width as libc::c_uint;
-        info.geometry.base_height = self.av_info.height as libc::c_uint;
-        info.geometry.max_width = self.av_info.max_width as libc::c_uint;
-        info.geometry.max_height = self.av_info.max_height as libc::c_uint;
-        info.geometry.aspect_ratio = self.av_info.aspect_ratio.unwrap_or( 0.0 );
-        info.timing.fps = self.av_info.frames_per_second;
-        info.timing.sample_rate = self.av_info.audio_sample_rate;
+        *info = self.av_info.to_raw();
     }
 
-    pub fn on_set_controller_port_device( &mut self, _port: libc::c_uint, _device: libc::c_uint ) {
+    pub fn on_set_controller_port_device( &mut self, port: libc::c_uint, device: libc::c_uint ) {
+        self.core.on_set_controller_port_device( port as u32, device as u32 );
     }
 
     pub fn on_reset( &mut self ) {
         self.core.on_reset();
     }
 
-    pub fn on_load_game( &mut self, game_info: *const libretro_sys::GameInfo ) -> bool {
+    pub unsafe fn on_load_game( &mut self, game_info: *const libretro_sys::GameInfo ) -> bool {
         assert_eq!( self.is_game_loaded, false );
 
-        let game_info = if game_info == ptr::null() {
-            None
-        } else {
-            Some( unsafe { &*game_info } )
-        };
+        let game_info_ext = query_game_info_ext();
+        let game_data = unsafe { GameData::from_raw( game_info, game_info_ext ) };
+        let result = self.core.on_load_game( game_data );
+        self.finish_loading( result )
+    }
 
-        let game_data = match game_info {
-            Some( game_info ) => {
-                let path = if game_info.path == ptr::null() {
-                    None
-                } else {
-                    unsafe {
-                        CStr::from_ptr( game_info.path ).to_str().ok().map( |path| path.to_owned() )
+    fn finish_loading( &mut self, result: Result< AudioVideoInfo, LoadGameError< B::Error > > ) -> bool {
+        match result {
+            Ok( av_info ) => {
+                #[cfg(feature = "hw-render")]
+                let mut av_info = av_info;
+                #[cfg(feature = "hw-render")]
+                {
+                    if let Some( context ) = av_info.hardware_context.take() {
+                        let hooks = hw_render::ContextHooks {
+                            instance: self as *mut Self as *mut libc::c_void,
+                            on_reset: Self::on_hw_context_reset,
+                            on_destroy: Self::on_hw_context_destroyed
+                        };
+
+                        if hw_render::register( context, hooks ).is_err() {
+                            let _ = self.core.on_unload_game();
+                            return false;
+                        }
                     }
-                };
+                }
 
-                let data = if game_info.data == ptr::null() && game_info.size == 0 {
-                    None
-                } else {
-                    unsafe {
-                        Some( slice::from_raw_parts( game_info.data as *const u8, game_info.size ) )
+                self.av_info = av_info;
+                let pixel_format = self.av_info.pixel_format;
+                self.output_pixel_format = match negotiate_pixel_format( pixel_format ) {
+                    Some( output_pixel_format ) => output_pixel_format,
+                    None => {
+                        logging::log( LogLevel::Error, &format!( "The frontend doesn't support the {:?} pixel format", pixel_format ) );
+                        let _ = self.core.on_unload_game();
+                        return false;
                     }
                 };
 
-                GameData {
-                    path: path,
-                    data: data
+                if self.output_pixel_format != pixel_format {
+                    logging::log( LogLevel::Warn, &format!( "The frontend doesn't support the {:?} pixel format; frames will be converted to {:?}", pixel_format, self.output_pixel_format ) );
                 }
-            },
-            None => {
-                GameData {
-                    path: None,
-                    data: None
+
+                Self::core_info().input_descriptors.register();
+                if let Some( level ) = Self::core_info().performance_level {
+                    let level = level as libc::c_uint;
+                    unsafe {
+                        let _ = call_environment( libretro_sys::ENVIRONMENT_SET_PERFORMANCE_LEVEL, &level );
+                    }
                 }
-            }
-        };
 
-        let result = self.core.on_load_game( game_data );
-        match result {
-            LoadGameResult::Success( av_info ) => {
-                self.av_info = av_info;
-                unsafe {
-                    let pixel_format = self.av_info.pixel_format;
-                    self.call_environment( libretro_sys::ENVIRONMENT_SET_PIXEL_FORMAT, &pixel_format ).unwrap();
+                let context = FrontendContext::new();
+                self.supports_input_bitmasks = context.supports_input_bitmasks();
+                self.can_dupe = context.can_dupe_video_frame();
+                self.max_users = context.max_users().unwrap_or_else( || max( Self::core_info().controller_info.port_count() as u32, 1 ) );
+                self.sensors = context.sensors();
+                self.midi = context.midi();
+                #[cfg(feature = "options")]
+                {
+                    self.option_values = Self::read_option_values();
                 }
 
                 self.is_game_loaded = true;
                 true
             },
-            LoadGameResult::Failed( _ ) => false
+            Err( error ) => {
+                if let Some( error ) = error.error {
+                    logging::log( LogLevel::Error, &format!( "Failed to load the game: {}", error ) );
+                }
+
+                false
+            }
         }
     }
 
-    pub fn on_load_game_special( &mut self, _game_type: libc::c_uint, _info: *const libretro_sys::GameInfo, _num_info: libc::size_t ) -> bool {
-        false
+    #[cfg(feature = "hw-render")]
+    unsafe fn on_hw_context_reset( instance: *mut libc::c_void ) {
+        let retro = &mut *(instance as *mut Self);
+        retro.core.on_hw_context_reset();
+
+        #[cfg(feature = "glow")]
+        {
+            if let Some( context ) = hw_render::create_glow_context() {
+                retro.core.on_glow_context_reset( context );
+            }
+        }
+    }
+
+    #[cfg(feature = "hw-render")]
+    unsafe fn on_hw_context_destroyed( instance: *mut libc::c_void ) {
+        let retro = &mut *(instance as *mut Self);
+        retro.core.on_hw_context_destroyed();
+    }
+
+    pub unsafe fn on_load_game_special( &mut self, game_type: libc::c_uint, info: *const libretro_sys::GameInfo, num_info: libc::size_t ) -> bool {
+        assert_eq!( self.is_game_loaded, false );
+
+        let game_info_ext = query_game_info_ext();
+        let games = (0..num_info).map( |index| unsafe {
+            let ext = if game_info_ext == ptr::null() { ptr::null() } else { game_info_ext.offset( index as isize ) };
+            GameData::from_raw( info.offset( index as isize ), ext )
+        }).collect();
+        let result = self.core.on_load_game_special( game_type as u32, games );
+        self.finish_loading( result )
     }
 
     pub fn on_run( &mut self ) {
+        let mut audio_video_enable = AUDIO_VIDEO_ENABLE_VIDEO | AUDIO_VIDEO_ENABLE_AUDIO;
+        unsafe {
+            if call_environment_mut( ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE, &mut audio_video_enable ).is_err() {
+                audio_video_enable = AUDIO_VIDEO_ENABLE_VIDEO | AUDIO_VIDEO_ENABLE_AUDIO;
+            }
+        }
+
         let mut handle = RuntimeHandle {
             video_refresh_callback: self.video_refresh_callback.unwrap(),
             input_state_callback: self.input_state_callback.unwrap(),
             audio_sample_batch_callback: self.audio_sample_batch_callback.unwrap(),
             upload_video_frame_already_called: false,
             audio_samples_uploaded: 0,
+            supports_input_bitmasks: self.supports_input_bitmasks,
+            can_dupe: self.can_dupe,
+            max_users: self.max_users,
+            sensors: self.sensors,
+            midi: self.midi,
+            joypad_states: Vec::new(),
+            previous_joypad_states: mem::replace( &mut self.previous_joypad_states, Vec::new() ),
+            fallback_frame_buffer: mem::replace( &mut self.fallback_frame_buffer, Vec::new() ),
+            conversion_buffer: mem::replace( &mut self.conversion_buffer, Vec::new() ),
+            new_av_info: None,
+            audio_video_enable: audio_video_enable,
 
             video_width: self.av_info.width,
             video_height: self.av_info.height,
+            video_max_width: self.av_info.max_width,
+            video_max_height: self.av_info.max_height,
+            video_aspect_ratio: self.av_info.aspect_ratio,
+            video_pixel_format: self.av_info.pixel_format,
+            video_output_pixel_format: self.output_pixel_format,
             video_frame_bytes_per_pixel: match self.av_info.pixel_format {
                 PixelFormat::ARGB1555 | PixelFormat::RGB565 => 2,
                 PixelFormat::ARGB8888 => 4
@@ -404,34 +1142,134 @@ impl< B: Core > Retro< B > {
             self.input_poll_callback.unwrap()();
         }
 
+        #[cfg(feature = "options")]
+        self.dispatch_option_changes();
+        self.dispatch_keyboard_events();
+        #[cfg(feature = "cheats")]
+        {
+            if self.cheats.is_empty() == false {
+                if let Some( memory ) = self.core.system_memory() {
+                    self.cheats.apply( memory );
+                }
+            }
+        }
+
         self.core.on_run( &mut handle );
+        environment::advance_emulated_clock( self.av_info.frames_per_second );
+        if Self::core_info().check_determinism {
+            self.determinism_checker.check( &mut self.core );
+        }
+
+        self.previous_joypad_states = handle.finish_joypad_states();
+        self.fallback_frame_buffer = mem::replace( &mut handle.fallback_frame_buffer, Vec::new() );
+        self.conversion_buffer = mem::replace( &mut handle.conversion_buffer, Vec::new() );
+        if let Some( av_info ) = handle.new_av_info.take() {
+            self.av_info = av_info;
+        }
+        self.av_info.width = handle.video_width;
+        self.av_info.height = handle.video_height;
+        self.av_info.aspect_ratio = handle.video_aspect_ratio;
+
+        let audio_pacing = self.av_info.effective_audio_pacing();
+        if handle.is_audio_enabled() == false || audio_pacing == AudioPacing::Disabled {
+            // The frontend is going to throw the audio away anyway.
+            self.total_audio_samples_uploaded = 0;
+            return;
+        }
 
         self.total_audio_samples_uploaded += handle.audio_samples_uploaded;
-        let required_audio_sample_count_per_frame = (self.av_info.audio_sample_rate / self.av_info.frames_per_second) * 2.0;
-        assert!(
-            self.total_audio_samples_uploaded as f64 >= required_audio_sample_count_per_frame,
-            format!( "You need to upload at least {} audio samples each frame!", required_audio_sample_count_per_frame )
-        );
+        let required_audio_sample_count_per_frame = (self.av_info.effective_audio_sample_rate() / self.av_info.frames_per_second) * 2.0;
+        if (self.total_audio_samples_uploaded as f64) < required_audio_sample_count_per_frame {
+            match audio_pacing {
+                AudioPacing::Strict => {
+                    panic!( "You need to upload at least {} audio samples each frame!", required_audio_sample_count_per_frame );
+                },
+                AudioPacing::Warn => {
+                    logging::log( LogLevel::Warn, &format!( "You need to upload at least {} audio samples each frame!", required_audio_sample_count_per_frame ) );
+                },
+                AudioPacing::PadWithSilence => {
+                    let missing = (required_audio_sample_count_per_frame - self.total_audio_samples_uploaded as f64).ceil() as usize;
+                    let missing = missing + missing % 2;
+                    let silence = [0_i16; AUDIO_CONVERSION_CHUNK_SIZE];
+                    let mut remaining = missing;
+                    while remaining > 0 {
+                        let count = min( remaining, silence.len() );
+                        if handle.upload_audio_frame( &silence[ ..count ] ).is_err() {
+                            break;
+                        }
+
+                        remaining -= count;
+                    }
+
+                    self.total_audio_samples_uploaded += missing;
+                },
+                AudioPacing::Disabled => {}
+            }
+        }
 
-        self.total_audio_samples_uploaded -= required_audio_sample_count_per_frame as usize;
+        self.total_audio_samples_uploaded = self.total_audio_samples_uploaded.saturating_sub( required_audio_sample_count_per_frame as usize );
     }
 
     pub fn on_serialize_size( &mut self ) -> libc::size_t {
-        0
+        self.core.serialize_size( SavestateContext::query() ) as libc::size_t
     }
 
-    pub fn on_serialize( &mut self, _data: *mut libc::c_void, _size: libc::size_t ) -> bool {
-        false
+    pub fn on_serialize( &mut self, data: *mut libc::c_void, size: libc::size_t ) -> bool {
+        let context = SavestateContext::query();
+        if data == ptr::null_mut() || self.core.serialize_size( context ) == 0 {
+            return false;
+        }
+
+        let buffer = unsafe { slice::from_raw_parts_mut( data as *mut u8, size ) };
+        match self.core.serialize( buffer, context ) {
+            Ok(()) => true,
+            Err( error ) => {
+                logging::log( LogLevel::Error, &format!( "Failed to save the state: {}", error ) );
+                false
+            }
+        }
     }
 
-    pub fn on_unserialize( &mut self, _data: *const libc::c_void, _size: libc::size_t ) -> bool {
-        false
+    pub fn on_unserialize( &mut self, data: *const libc::c_void, size: libc::size_t ) -> bool {
+        let context = SavestateContext::query();
+        if data == ptr::null() || self.core.serialize_size( context ) == 0 {
+            return false;
+        }
+
+        let buffer = unsafe { slice::from_raw_parts( data as *const u8, size ) };
+        match self.core.unserialize( buffer, context ) {
+            Ok(()) => true,
+            Err( error ) => {
+                logging::log( LogLevel::Error, &format!( "Failed to load the state: {}", error ) );
+                false
+            }
+        }
     }
 
     pub fn on_cheat_reset( &mut self ) {
+        #[cfg(feature = "cheats")]
+        self.cheats.reset();
+        self.core.on_cheat_reset();
     }
 
-    pub fn on_cheat_set( &mut self, _index: libc::c_uint, _is_enabled: bool, _code: *const libc::c_char ) {
+    pub unsafe fn on_cheat_set( &mut self, index: libc::c_uint, is_enabled: bool, code: *const libc::c_char ) {
+        if code == ptr::null() {
+            return;
+        }
+
+        let code = unsafe { CStr::from_ptr( code ) }.to_string_lossy();
+        #[cfg(feature = "cheats")]
+        {
+            if Self::core_info().has_builtin_cheats {
+                if let Err( error ) = self.cheats.set( index as u32, is_enabled, &code ) {
+                    logging::log( LogLevel::Warn, &error.to_string() );
+                }
+            }
+        }
+
+        if let Err( error ) = self.core.on_cheat_set( index as u32, is_enabled, &code ) {
+            logging::log( LogLevel::Warn, &format!( "Failed to apply cheat #{}: {}", index, error ) );
+        }
     }
 
     pub fn on_unload_game( &mut self ) {
@@ -452,7 +1290,8 @@ impl< B: Core > Retro< B > {
             libretro_sys::MEMORY_RTC => self.core.rtc_memory(),
             libretro_sys::MEMORY_SYSTEM_RAM => self.core.system_memory(),
             libretro_sys::MEMORY_VIDEO_RAM => self.core.video_memory(),
-            _ => unreachable!(),
+            // The frontend is free to ask for any other kind of memory.
+            _ => None
         }
     }
 
@@ -475,247 +1314,74 @@ pub struct RuntimeHandle {
     audio_sample_batch_callback: libretro_sys::AudioSampleBatchFn,
     upload_video_frame_already_called: bool,
     audio_samples_uploaded: usize,
+    supports_input_bitmasks: bool,
+    can_dupe: bool,
+    max_users: u32,
+    sensors: Option< Sensors >,
+    midi: Option< Midi >,
+    joypad_states: Vec< Option< JoypadState > >,
+    previous_joypad_states: Vec< JoypadState >,
+    fallback_frame_buffer: Vec< u8 >,
+    conversion_buffer: Vec< u8 >,
+
+    new_av_info: Option< AudioVideoInfo >,
+    audio_video_enable: libc::c_int,
 
     video_width: u32,
     video_height: u32,
+    video_max_width: u32,
+    video_max_height: u32,
+    video_aspect_ratio: Option< f32 >,
+    video_pixel_format: PixelFormat,
+    // What the frontend actually accepted; frames are converted if it's different.
+    video_output_pixel_format: PixelFormat,
     video_frame_bytes_per_pixel: u32
 }
 
 impl RuntimeHandle {
-    pub fn upload_video_frame( &mut self, data: &[u8] ) {
-        assert!( self.upload_video_frame_already_called == false, "You can only call upload_video_frame() once per frame!" );
-        assert!( data.len() as u32 >= self.video_width * self.video_height * self.video_frame_bytes_per_pixel, "Data too small to upload!" );
-
-        self.upload_video_frame_already_called = true;
-        let bytes = data.as_ptr() as *const libc::c_void;
-        let width = self.video_width as libc::c_uint;
-        let height = self.video_height as libc::c_uint;
-        let bytes_per_line = (self.video_width * self.video_frame_bytes_per_pixel) as usize;
-        unsafe {
-            (self.video_refresh_callback)( bytes, width, height, bytes_per_line );
-        }
+    pub fn is_video_enabled( &self ) -> bool {
+        self.audio_video_enable & AUDIO_VIDEO_ENABLE_VIDEO != 0
     }
 
-    pub fn upload_audio_frame( &mut self, data: &[i16] ) {
-        assert!( data.len() % 2 == 0, "Audio data must be in stereo!" );
-
-        self.audio_samples_uploaded += data.len();
-        unsafe {
-            (self.audio_sample_batch_callback)( data.as_ptr(), data.len() / 2 );
-        }
-    }
-
-    pub fn is_joypad_button_pressed( &mut self, port: u32, button: JoypadButton ) -> bool {
-        let device_id = match button {
-            JoypadButton::A => libretro_sys::DEVICE_ID_JOYPAD_A,
-            JoypadButton::B => libretro_sys::DEVICE_ID_JOYPAD_B,
-            JoypadButton::X => libretro_sys::DEVICE_ID_JOYPAD_X,
-            JoypadButton::Y => libretro_sys::DEVICE_ID_JOYPAD_Y,
-            JoypadButton::Start => libretro_sys::DEVICE_ID_JOYPAD_START,
-            JoypadButton::Select => libretro_sys::DEVICE_ID_JOYPAD_SELECT,
-            JoypadButton::Left => libretro_sys::DEVICE_ID_JOYPAD_LEFT,
-            JoypadButton::Right => libretro_sys::DEVICE_ID_JOYPAD_RIGHT,
-            JoypadButton::Up => libretro_sys::DEVICE_ID_JOYPAD_UP,
-            JoypadButton::Down => libretro_sys::DEVICE_ID_JOYPAD_DOWN,
-            JoypadButton::L1 => libretro_sys::DEVICE_ID_JOYPAD_L,
-            JoypadButton::L2 => libretro_sys::DEVICE_ID_JOYPAD_L2,
-            JoypadButton::L3 => libretro_sys::DEVICE_ID_JOYPAD_L3,
-            JoypadButton::R1 => libretro_sys::DEVICE_ID_JOYPAD_R,
-            JoypadButton::R2 => libretro_sys::DEVICE_ID_JOYPAD_R2,
-            JoypadButton::R3 => libretro_sys::DEVICE_ID_JOYPAD_R3
-        };
-
-        unsafe {
-            let value = (self.input_state_callback)( port, libretro_sys::DEVICE_JOYPAD, 0, device_id );
-            return value == 1;
-        }
+    pub fn is_audio_enabled( &self ) -> bool {
+        self.audio_video_enable & AUDIO_VIDEO_ENABLE_AUDIO != 0
     }
-}
-
-#[doc(hidden)]
-pub fn construct< T: 'static + Core >() -> Retro< T > {
-    Retro::new( T::default() )
-}
-
-#[macro_export]
-macro_rules! libretro_core {
-    ($core: path) => (
-        #[doc(hidden)]
-        static mut LIBRETRO_INSTANCE: *mut $crate::Retro< $core > = 0 as *mut $crate::Retro< $core >;
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub extern "C" fn retro_api_version() -> $crate::libc::c_uint {
-            return $crate::libretro_sys::API_VERSION;
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_init() {
-            assert_eq!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            let retro = $crate::construct::< $core >();
-            LIBRETRO_INSTANCE = Box::into_raw( Box::new( retro ) );
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_deinit() {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            let instance = Box::from_raw( LIBRETRO_INSTANCE );
-            LIBRETRO_INSTANCE = 0 as *mut _;
-            ::std::mem::drop( instance );
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_environment( callback: $crate::libretro_sys::EnvironmentFn ) {
-            $crate::Retro::< $core >::on_set_environment( callback )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_video_refresh( callback: $crate::libretro_sys::VideoRefreshFn ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_video_refresh( callback )
-        }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_audio_sample( callback: $crate::libretro_sys::AudioSampleFn ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_audio_sample( callback )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_audio_sample_batch( callback: $crate::libretro_sys::AudioSampleBatchFn ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_audio_sample_batch( callback )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_input_poll( callback: $crate::libretro_sys::InputPollFn ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_input_poll( callback )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_input_state( callback: $crate::libretro_sys::InputStateFn ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_input_state( callback )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub extern "C" fn retro_get_system_info( info: *mut $crate::libretro_sys::SystemInfo ) {
-            $crate::Retro::< $core >::on_get_system_info( info )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_get_system_av_info( info: *mut $crate::libretro_sys::SystemAvInfo ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_get_system_av_info( info )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_set_controller_port_device( port: $crate::libc::c_uint, device: $crate::libc::c_uint ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_set_controller_port_device( port, device )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_reset() {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_reset()
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_run() {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_run()
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_serialize_size() -> $crate::libc::size_t {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_serialize_size()
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_serialize( data: *mut $crate::libc::c_void, size: $crate::libc::size_t ) -> bool {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_serialize( data, size )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_unserialize( data: *const $crate::libc::c_void, size: $crate::libc::size_t ) -> bool {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_unserialize( data, size )
-        }
-
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_cheat_reset() {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_cheat_reset()
-        }
+    // Set when the frontend is only going to use the savestates
+    // made this frame within the same session, e.g. for runahead.
+    pub fn use_fast_savestates( &self ) -> bool {
+        self.audio_video_enable & AUDIO_VIDEO_ENABLE_FAST_SAVESTATES != 0
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_cheat_set( index: $crate::libc::c_uint, is_enabled: bool, code: *const $crate::libc::c_char ) {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_cheat_set( index, is_enabled, code )
-        }
+    pub fn is_audio_hard_disabled( &self ) -> bool {
+        self.audio_video_enable & AUDIO_VIDEO_ENABLE_HARD_DISABLE_AUDIO != 0
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_load_game( game: *const $crate::libretro_sys::GameInfo ) -> bool {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_load_game( game )
-        }
+    pub fn set_fast_forwarding( &mut self, fast_forwarding: FastForwarding ) -> Result< (), EnvError > {
+        environment::set_fast_forwarding( fast_forwarding )
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_load_game_special( game_type: $crate::libc::c_uint, info: *const $crate::libretro_sys::GameInfo, num_info: $crate::libc::size_t ) -> bool {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_load_game_special( game_type, info, num_info )
+    // Asks the frontend to close the core once the current frame is done.
+    pub fn shutdown( &mut self ) -> Result< (), EnvError > {
+        unsafe {
+            call_environment_raw( libretro_sys::ENVIRONMENT_SHUTDOWN, ptr::null_mut() )
         }
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_unload_game() {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_unload_game()
-        }
+    pub fn time_usec( &self ) -> i64 {
+        environment::frontend_time().as_usec()
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_get_region() -> $crate::libc::c_uint {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_get_region()
-        }
+    pub fn max_users( &self ) -> u32 {
+        self.max_users
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_get_memory_data( id: $crate::libc::c_uint ) -> *mut $crate::libc::c_void {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_get_memory_data( id )
-        }
+    pub fn sensors( &self ) -> Option< Sensors > {
+        self.sensors
+    }
 
-        #[doc(hidden)]
-        #[no_mangle]
-        pub unsafe extern "C" fn retro_get_memory_size( id: $crate::libc::c_uint ) -> $crate::libc::size_t {
-            assert_ne!( LIBRETRO_INSTANCE, 0 as *mut _ );
-            (&mut *LIBRETRO_INSTANCE).on_get_memory_size( id )
-        }
-    )
+    pub fn midi( &self ) -> Option< Midi > {
+        self.midi
+    }
 }
+
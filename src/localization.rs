@@ -0,0 +1,56 @@
+use std::sync::Mutex;
+
+use environment;
+use language::Language;
+use lock;
+
+pub type StringTable = &'static [(&'static str, &'static str)];
+
+struct State {
+    language: Option< Language >,
+    tables: Vec< (Language, StringTable) >
+}
+
+static STATE: Mutex< State > = Mutex::new( State {
+    language: None,
+    tables: Vec::new()
+});
+
+fn lookup( tables: &[(Language, StringTable)], language: Language, key: &str ) -> Option< &'static str > {
+    tables.iter()
+        .filter( |&&(table_language, _)| table_language == language )
+        .flat_map( |&(_, table)| table.iter() )
+        .find( |&&(table_key, _)| table_key == key )
+        .map( |&(_, value)| value )
+}
+
+pub fn register( language: Language, table: StringTable ) {
+    lock( &STATE ).tables.push( (language, table) );
+}
+
+// Overrides the language reported by the frontend.
+pub fn set_language( language: Language ) {
+    lock( &STATE ).language = Some( language );
+}
+
+// Falls back to English for as long as the frontend can't tell us its language.
+pub fn language() -> Language {
+    if let Some( language ) = lock( &STATE ).language {
+        return language;
+    }
+
+    match environment::get_language() {
+        Ok( language ) => *lock( &STATE ).language.get_or_insert( language ),
+        Err( _ ) => Language::English
+    }
+}
+
+// Looks the key up in the current language's tables, then in the
+// English ones, and if it isn't there either returns the key itself.
+pub fn tr( key: &'static str ) -> &'static str {
+    let language = language();
+    let state = lock( &STATE );
+    lookup( &state.tables, language, key )
+        .or_else( || lookup( &state.tables, Language::English, key ) )
+        .unwrap_or( key )
+}
@@ -0,0 +1,190 @@
+#[cfg(not(feature = "minimal"))]
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::ffi::CString;
+use std::panic;
+#[cfg(feature = "tracing")]
+use std::fmt::{self, Write};
+
+use libc;
+use libretro_sys;
+
+#[cfg(feature = "log")]
+use log_crate;
+#[cfg(feature = "tracing")]
+use tracing_core;
+#[cfg(feature = "tracing")]
+use tracing_subscriber;
+
+use environment::call_environment_mut;
+use message::{LogLevel, Message};
+use interfaces;
+use lock;
+
+pub(crate) type LogFn = unsafe extern "C" fn( level: libc::c_int, format: *const libc::c_char, ... );
+
+#[repr(C)]
+struct RawLogCallback {
+    log: Option< LogFn >
+}
+
+pub(crate) fn query() {
+    let mut callback = RawLogCallback {
+        log: None
+    };
+
+    let result = unsafe { call_environment_mut( libretro_sys::ENVIRONMENT_GET_LOG_INTERFACE, &mut callback ) };
+    let callback = match result {
+        Ok(()) => callback.log,
+        Err( _ ) => None
+    };
+
+    interfaces::with( |interfaces| *lock( &interfaces.log ) = callback );
+}
+
+// Goes to the frontend's log if it has one, and to stderr otherwise.
+pub fn log( level: LogLevel, message: &str ) {
+    let callback = interfaces::with( |interfaces| *lock( &interfaces.log ) );
+    let callback = match callback {
+        Some( callback ) => callback,
+        None => {
+            eprintln!( "[{:?}] {}", level, message );
+            return;
+        }
+    };
+
+    let message = match CString::new( message ) {
+        Ok( message ) => message,
+        Err( _ ) => CString::new( message.replace( '\0', "" ) ).unwrap()
+    };
+
+    unsafe {
+        callback( level.raw(), b"%s\n\0".as_ptr() as *const libc::c_char, message.as_ptr() );
+    }
+}
+
+// Reports panics through the frontend's log before handing them off to the
+// previously installed hook. Only panics on the thread the frontend is
+// calling into the core from are shown on screen, since the frontend isn't
+// expecting to be called from any other thread.
+//
+// The backtrace is only logged when it's enabled through `RUST_BACKTRACE`,
+// and never with the `minimal` feature.
+pub fn install_panic_hook() {
+    let previous_hook = panic::take_hook();
+    panic::set_hook( Box::new( move |info| {
+        let payload = info.payload();
+        let reason = if let Some( reason ) = payload.downcast_ref::< &str >() {
+            *reason
+        } else if let Some( reason ) = payload.downcast_ref::< String >() {
+            &reason[..]
+        } else {
+            "Box<Any>"
+        };
+
+        let message = match info.location() {
+            Some( location ) => format!( "Core panicked at {}:{}: {}", location.file(), location.line(), reason ),
+            None => format!( "Core panicked: {}", reason )
+        };
+
+        log( LogLevel::Error, &message );
+        #[cfg(not(feature = "minimal"))]
+        {
+            let backtrace = Backtrace::capture();
+            if backtrace.status() == BacktraceStatus::Captured {
+                log( LogLevel::Error, &format!( "{}", backtrace ) );
+            }
+        }
+
+        if interfaces::is_entered() {
+            let _ = Message::new( &message ).level( LogLevel::Error ).duration( 10000 ).show();
+        }
+
+        previous_hook( info );
+    }));
+}
+
+// A `log` backend which forwards everything to the frontend.
+#[cfg(feature = "log")]
+pub struct FrontendLogger;
+
+#[cfg(feature = "log")]
+impl log_crate::Log for FrontendLogger {
+    fn enabled( &self, _metadata: &log_crate::Metadata ) -> bool {
+        true
+    }
+
+    fn log( &self, record: &log_crate::Record ) {
+        let level = match record.level() {
+            log_crate::Level::Error => LogLevel::Error,
+            log_crate::Level::Warn => LogLevel::Warn,
+            log_crate::Level::Info => LogLevel::Info,
+            log_crate::Level::Debug | log_crate::Level::Trace => LogLevel::Debug
+        };
+
+        log( level, &format!( "{}", record.args() ) );
+    }
+
+    fn flush( &self ) {
+    }
+}
+
+#[cfg(feature = "log")]
+pub fn init( max_level: log_crate::LevelFilter ) -> Result< (), log_crate::SetLoggerError > {
+    static LOGGER: FrontendLogger = FrontendLogger;
+    log_crate::set_logger( &LOGGER )?;
+    log_crate::set_max_level( max_level );
+    Ok(())
+}
+
+// A `tracing_subscriber` layer which forwards events, prefixed with
+// the names of the spans they happened in, to the frontend.
+#[cfg(feature = "tracing")]
+pub struct FrontendLayer;
+
+#[cfg(feature = "tracing")]
+struct EventVisitor {
+    output: String
+}
+
+#[cfg(feature = "tracing")]
+impl tracing_core::field::Visit for EventVisitor {
+    fn record_debug( &mut self, field: &tracing_core::Field, value: &dyn fmt::Debug ) {
+        if field.name() == "message" {
+            let _ = write!( self.output, " {:?}", value );
+        } else {
+            let _ = write!( self.output, " {}={:?}", field.name(), value );
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl< S > tracing_subscriber::Layer< S > for FrontendLayer
+    where S: tracing_core::Subscriber + for< 'a > tracing_subscriber::registry::LookupSpan< 'a >
+{
+    fn on_event( &self, event: &tracing_core::Event, context: tracing_subscriber::layer::Context< S > ) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            tracing_core::Level::ERROR => LogLevel::Error,
+            tracing_core::Level::WARN => LogLevel::Warn,
+            tracing_core::Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug
+        };
+
+        let mut visitor = EventVisitor {
+            output: String::new()
+        };
+
+        if let Some( scope ) = context.event_scope( event ) {
+            for span in scope.from_root() {
+                let _ = write!( visitor.output, "{}:", span.name() );
+            }
+        }
+
+        if visitor.output.is_empty() {
+            visitor.output.push_str( metadata.target() );
+        }
+
+        event.record( &mut visitor );
+        log( level, &visitor.output );
+    }
+}
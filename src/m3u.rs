@@ -0,0 +1,249 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use disk_control::DiskControl;
+use filesystem::{self, OpenMode};
+
+const SAVE_DISK_DIRECTIVE: &str = "#SAVEDISK:";
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DiskImage {
+    pub path: PathBuf,
+    pub label: Option< String >,
+    // Declared with `#SAVEDISK:`; the file might not exist yet.
+    pub is_save_disk: bool
+}
+
+// An ordered set of disc images, usually loaded from an `.m3u` playlist,
+// which can be handed to the frontend as-is through `Core::disk_control`.
+//
+// The frontend tells us which image was used last time before the game
+// is loaded, so the set should be created up front and only filled in
+// by `load` once the game's path is known.
+#[derive(Clone, Default, Debug)]
+pub struct DiskSet {
+    images: Vec< DiskImage >,
+    index: u32,
+    is_ejected: bool,
+    initial_image: Option< (u32, String) >
+}
+
+impl DiskSet {
+    pub fn new() -> DiskSet {
+        DiskSet::default()
+    }
+
+    pub fn set_images( &mut self, images: Vec< DiskImage > ) {
+        self.images = images;
+        self.index = 0;
+        self.is_ejected = false;
+
+        if let Some( (index, path) ) = self.initial_image.take() {
+            let is_same_image = self.images.get( index as usize ).map( |image| image.path == Path::new( &path ) ).unwrap_or( false );
+            if is_same_image {
+                self.index = index;
+            }
+        }
+    }
+
+    // Loads the playlist if the path points to one; otherwise
+    // the set will contain only the given image.
+    pub fn load< P: AsRef< Path > >( &mut self, path: P ) -> io::Result< () > {
+        let path = path.as_ref();
+        let is_playlist = path.extension().map( |extension| extension.eq_ignore_ascii_case( "m3u" ) ).unwrap_or( false );
+        if is_playlist == false {
+            self.set_images( vec![ DiskImage {
+                path: path.to_owned(),
+                label: None,
+                is_save_disk: false
+            }]);
+            return Ok(());
+        }
+
+        let mut contents = String::new();
+        filesystem::get().open( path, OpenMode::Read )?.read_to_string( &mut contents )?;
+
+        let base = path.parent().unwrap_or( Path::new( "" ) );
+        self.set_images( parse( &contents, base ) );
+        Ok(())
+    }
+
+    pub fn images( &self ) -> &[DiskImage] {
+        &self.images
+    }
+
+    pub fn current_image( &self ) -> Option< &DiskImage > {
+        if self.is_ejected {
+            return None;
+        }
+
+        self.images.get( self.index as usize )
+    }
+}
+
+// Paths are relative to `base` unless they're absolute; a label
+// can be given after a `|`, e.g. `disc2.cue|Disc 2 (Japan)`.
+pub fn parse( contents: &str, base: &Path ) -> Vec< DiskImage > {
+    let contents = contents.trim_start_matches( '\u{feff}' );
+    let mut images = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with( SAVE_DISK_DIRECTIVE ) {
+            let name = line[ SAVE_DISK_DIRECTIVE.len().. ].trim();
+            if name.is_empty() == false {
+                images.push( DiskImage {
+                    path: base.join( name ),
+                    label: Some( name.to_owned() ),
+                    is_save_disk: true
+                });
+            }
+            continue;
+        }
+
+        if line.starts_with( '#' ) {
+            continue;
+        }
+
+        let (path, label) = match line.find( '|' ) {
+            Some( position ) => {
+                let label = line[ position + 1.. ].trim();
+                (line[ ..position ].trim(), if label.is_empty() { None } else { Some( label.to_owned() ) })
+            },
+            None => (line, None)
+        };
+
+        images.push( DiskImage {
+            path: base.join( path ),
+            label: label,
+            is_save_disk: false
+        });
+    }
+
+    images
+}
+
+impl DiskControl for DiskSet {
+    fn is_ejected( &self ) -> bool {
+        self.is_ejected
+    }
+
+    fn set_ejected( &mut self, is_ejected: bool ) -> bool {
+        self.is_ejected = is_ejected;
+        true
+    }
+
+    fn image_index( &self ) -> u32 {
+        self.index
+    }
+
+    fn set_image_index( &mut self, index: u32 ) -> bool {
+        if index as usize > self.images.len() {
+            return false;
+        }
+
+        self.index = index;
+        true
+    }
+
+    fn image_count( &self ) -> u32 {
+        self.images.len() as u32
+    }
+
+    fn replace_image( &mut self, index: u32, path: Option< &str > ) -> bool {
+        let index = index as usize;
+        if index >= self.images.len() {
+            return false;
+        }
+
+        match path {
+            Some( path ) => {
+                self.images[ index ] = DiskImage {
+                    path: PathBuf::from( path ),
+                    label: None,
+                    is_save_disk: false
+                };
+            },
+            None => {
+                self.images.remove( index );
+                if (self.index as usize) > index {
+                    self.index -= 1;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn add_image( &mut self ) -> bool {
+        self.images.push( DiskImage {
+            path: PathBuf::new(),
+            label: None,
+            is_save_disk: false
+        });
+
+        true
+    }
+
+    fn set_initial_image( &mut self, index: u32, path: &str ) -> bool {
+        self.initial_image = Some( (index, path.to_owned()) );
+        true
+    }
+
+    fn image_path( &self, index: u32 ) -> Option< String > {
+        self.images.get( index as usize ).and_then( |image| image.path.to_str() ).map( |path| path.to_owned() )
+    }
+
+    fn image_label( &self, index: u32 ) -> Option< String > {
+        let image = self.images.get( index as usize )?;
+        image.label.clone().or_else( || image.path.file_stem().and_then( |name| name.to_str() ).map( |name| name.to_owned() ) )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image( path: &str, label: Option< &str >, is_save_disk: bool ) -> DiskImage {
+        DiskImage {
+            path: PathBuf::from( path ),
+            label: label.map( |label| label.to_owned() ),
+            is_save_disk: is_save_disk
+        }
+    }
+
+    #[test]
+    fn parse_playlist() {
+        let contents = "\u{feff}#EXTM3U\r\ndisc1.cue\r\n\r\n  disc2.cue | Disc 2 (Japan)  \r\ndisc3.cue|\r\n#SAVEDISK:save.bin\r\n/absolute/disc4.cue\r\n";
+        let images = parse( contents, Path::new( "games" ) );
+        assert_eq!( images, vec![
+            image( "games/disc1.cue", None, false ),
+            image( "games/disc2.cue", Some( "Disc 2 (Japan)" ), false ),
+            image( "games/disc3.cue", None, false ),
+            image( "games/save.bin", Some( "save.bin" ), true ),
+            image( "/absolute/disc4.cue", None, false )
+        ]);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_empty_save_disks() {
+        let images = parse( "# comment\n#SAVEDISK:\n#SAVEDISK:   \n", Path::new( "" ) );
+        assert!( images.is_empty() );
+    }
+
+    #[test]
+    fn initial_image_is_kept_only_if_it_matches() {
+        let mut set = DiskSet::new();
+        set.set_initial_image( 1, "games/disc2.cue" );
+        set.set_images( parse( "disc1.cue\ndisc2.cue\n", Path::new( "games" ) ) );
+        assert_eq!( set.current_image(), Some( &image( "games/disc2.cue", None, false ) ) );
+
+        let mut set = DiskSet::new();
+        set.set_initial_image( 1, "games/other.cue" );
+        set.set_images( parse( "disc1.cue\ndisc2.cue\n", Path::new( "games" ) ) );
+        assert_eq!( set.current_image(), Some( &image( "games/disc1.cue", None, false ) ) );
+    }
+}
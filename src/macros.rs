@@ -0,0 +1,397 @@
+use std::mem;
+use std::ptr;
+use std::panic;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use libretro_sys;
+
+use environment::call_environment_raw;
+use context::FrontendContext;
+use logging;
+use message::LogLevel;
+use {Core, Retro};
+
+#[doc(hidden)]
+pub fn construct< T: Core >( core: T ) -> Retro< T > {
+    Retro::new( core )
+}
+
+#[doc(hidden)]
+pub fn create< T: Core, F: FnOnce() -> T >( fallback: F ) -> Retro< T > {
+    let core = T::create( &FrontendContext::new() ).unwrap_or_else( fallback );
+    Retro::new( core )
+}
+
+// Unwinding across an `extern "C"` boundary is undefined behavior,
+// so every entry point goes through this.
+#[doc(hidden)]
+pub fn catch_panic< R, F: FnOnce() -> R >( callback: F ) -> Option< R > {
+    panic::catch_unwind( panic::AssertUnwindSafe( callback ) ).ok()
+}
+
+// Owns the instance created in `retro_init`; the frontend only ever calls
+// into the core from one thread at a time, which is what makes `with` sound.
+//
+// Every `libretro_core!` invocation gets exactly one of these, since the C
+// entry points don't carry any user data; running several instances side by
+// side needs either several prefixed invocations or driving `Retro` through
+// `Retro::enter` directly.
+#[doc(hidden)]
+pub struct Instance< B: Core > {
+    pointer: AtomicPtr< Retro< B > >
+}
+
+impl< B: Core > Instance< B > {
+    pub const fn new() -> Self {
+        Instance {
+            pointer: AtomicPtr::new( 0 as *mut Retro< B > )
+        }
+    }
+
+    // A frontend calling `retro_init` twice without a `retro_deinit` in
+    // between keeps the instance it already has.
+    pub fn set( &self, instance: Box< Retro< B > > ) -> bool {
+        let instance = Box::into_raw( instance );
+        if let Err( _ ) = self.pointer.compare_exchange( ptr::null_mut(), instance, Ordering::AcqRel, Ordering::Acquire ) {
+            unsafe { mem::drop( Box::from_raw( instance ) ); }
+            logging::log( LogLevel::Error, "The core was initialized twice; ignoring the second `retro_init`" );
+            return false;
+        }
+
+        true
+    }
+
+    pub fn take( &self ) -> Option< Box< Retro< B > > > {
+        let instance = self.pointer.swap( ptr::null_mut(), Ordering::AcqRel );
+        if instance.is_null() {
+            None
+        } else {
+            Some( unsafe { Box::from_raw( instance ) } )
+        }
+    }
+
+    pub unsafe fn with< R, F: FnOnce( &mut Retro< B > ) -> R >( &self, callback: F ) -> R {
+        let instance = self.pointer.load( Ordering::Acquire );
+        assert_ne!( instance, ptr::null_mut(), "The core is not initialized!" );
+        (&mut *instance).enter( callback )
+    }
+}
+
+#[doc(hidden)]
+pub fn request_shutdown() {
+    unsafe {
+        let _ = call_environment_raw( libretro_sys::ENVIRONMENT_SHUTDOWN, ptr::null_mut() );
+    }
+}
+
+#[macro_export]
+macro_rules! libretro_core {
+    ($core: path) => (
+        $crate::libretro_core!( $core => <$core as ::std::default::Default>::default(), prefix = "" );
+    );
+
+    // Statically linked cores (e.g. on consoles or iOS) need their
+    // symbols prefixed, e.g. `prefix = "mygame_"` exports `mygame_retro_run`.
+    ($core: path, prefix = $prefix: expr) => (
+        $crate::libretro_core!( $core => <$core as ::std::default::Default>::default(), prefix = $prefix );
+    );
+
+    // The expression is evaluated in `retro_init`, after the frontend has
+    // already given us its environment callback, and only if `Core::create`
+    // returns `None`.
+    ($core: path => $constructor: expr) => (
+        $crate::libretro_core!( $core => $constructor, prefix = "" );
+    );
+
+    ($core: path => $constructor: expr, prefix = $prefix: expr) => (
+        $crate::libretro_core!( @instance $core );
+        $crate::libretro_core!( @entry retro_api_version, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_init, $core => $constructor, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_deinit, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_environment, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_video_refresh, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_audio_sample, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_audio_sample_batch, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_input_poll, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_input_state, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_get_system_info, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_get_system_av_info, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_set_controller_port_device, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_reset, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_run, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_serialize_size, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_serialize, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_unserialize, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_cheat_reset, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_cheat_set, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_load_game, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_load_game_special, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_unload_game, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_get_region, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_get_memory_data, $core, prefix = $prefix );
+        $crate::libretro_core!( @entry retro_get_memory_size, $core, prefix = $prefix );
+    );
+
+    // The pieces the above is made of, which `#[libretro_core]` puts together
+    // by itself since it can leave out some of the entry points.
+    (@instance $core: path) => (
+        #[doc(hidden)]
+        static LIBRETRO_INSTANCE: $crate::Instance< $core > = $crate::Instance::new();
+    );
+
+    (@entry retro_api_version, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_api_version" )]
+        pub extern "C" fn retro_api_version() -> $crate::libc::c_uint {
+            return $crate::libretro_sys::API_VERSION;
+        }
+    );
+
+    (@entry retro_init, $core: path => $constructor: expr, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_init" )]
+        pub unsafe extern "C" fn retro_init() {
+            let _ = $crate::catch_panic( || {
+                let retro = $crate::create::< $core, _ >( || $constructor );
+                if LIBRETRO_INSTANCE.set( Box::new( retro ) ) {
+                    LIBRETRO_INSTANCE.with( |retro| retro.on_init() );
+                }
+            });
+        }
+    );
+
+    (@entry retro_deinit, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_deinit" )]
+        pub unsafe extern "C" fn retro_deinit() {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_deinit() );
+                ::std::mem::drop( LIBRETRO_INSTANCE.take() );
+            });
+        }
+    );
+
+    (@entry retro_set_environment, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_environment" )]
+        pub unsafe extern "C" fn retro_set_environment( callback: $crate::libretro_sys::EnvironmentFn ) {
+            let _ = $crate::catch_panic( || {
+                $crate::Retro::< $core >::on_set_environment( callback )
+            });
+        }
+    );
+
+    (@entry retro_set_video_refresh, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_video_refresh" )]
+        pub unsafe extern "C" fn retro_set_video_refresh( callback: $crate::libretro_sys::VideoRefreshFn ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_video_refresh( callback ) )
+            });
+        }
+    );
+
+    (@entry retro_set_audio_sample, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_audio_sample" )]
+        pub unsafe extern "C" fn retro_set_audio_sample( callback: $crate::libretro_sys::AudioSampleFn ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_audio_sample( callback ) )
+            });
+        }
+    );
+
+    (@entry retro_set_audio_sample_batch, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_audio_sample_batch" )]
+        pub unsafe extern "C" fn retro_set_audio_sample_batch( callback: $crate::libretro_sys::AudioSampleBatchFn ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_audio_sample_batch( callback ) )
+            });
+        }
+    );
+
+    (@entry retro_set_input_poll, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_input_poll" )]
+        pub unsafe extern "C" fn retro_set_input_poll( callback: $crate::libretro_sys::InputPollFn ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_input_poll( callback ) )
+            });
+        }
+    );
+
+    (@entry retro_set_input_state, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_input_state" )]
+        pub unsafe extern "C" fn retro_set_input_state( callback: $crate::libretro_sys::InputStateFn ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_input_state( callback ) )
+            });
+        }
+    );
+
+    (@entry retro_get_system_info, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_get_system_info" )]
+        pub extern "C" fn retro_get_system_info( info: *mut $crate::libretro_sys::SystemInfo ) {
+            let _ = $crate::catch_panic( || {
+                unsafe { $crate::Retro::< $core >::on_get_system_info( info ) }
+            });
+        }
+    );
+
+    (@entry retro_get_system_av_info, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_get_system_av_info" )]
+        pub unsafe extern "C" fn retro_get_system_av_info( info: *mut $crate::libretro_sys::SystemAvInfo ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| unsafe { retro.on_get_system_av_info( info ) } )
+            });
+        }
+    );
+
+    (@entry retro_set_controller_port_device, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_set_controller_port_device" )]
+        pub unsafe extern "C" fn retro_set_controller_port_device( port: $crate::libc::c_uint, device: $crate::libc::c_uint ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_set_controller_port_device( port, device ) )
+            });
+        }
+    );
+
+    (@entry retro_reset, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_reset" )]
+        pub unsafe extern "C" fn retro_reset() {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_reset() )
+            });
+        }
+    );
+
+    (@entry retro_run, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_run" )]
+        pub unsafe extern "C" fn retro_run() {
+            let result = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_run() )
+            });
+
+            if result.is_none() {
+                $crate::request_shutdown();
+            }
+        }
+    );
+
+    (@entry retro_serialize_size, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_serialize_size" )]
+        pub unsafe extern "C" fn retro_serialize_size() -> $crate::libc::size_t {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_serialize_size() )
+            }).unwrap_or( 0 )
+        }
+    );
+
+    (@entry retro_serialize, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_serialize" )]
+        pub unsafe extern "C" fn retro_serialize( data: *mut $crate::libc::c_void, size: $crate::libc::size_t ) -> bool {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_serialize( data, size ) )
+            }).unwrap_or( false )
+        }
+    );
+
+    (@entry retro_unserialize, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_unserialize" )]
+        pub unsafe extern "C" fn retro_unserialize( data: *const $crate::libc::c_void, size: $crate::libc::size_t ) -> bool {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_unserialize( data, size ) )
+            }).unwrap_or( false )
+        }
+    );
+
+    (@entry retro_cheat_reset, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_cheat_reset" )]
+        pub unsafe extern "C" fn retro_cheat_reset() {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_cheat_reset() )
+            });
+        }
+    );
+
+    (@entry retro_cheat_set, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_cheat_set" )]
+        pub unsafe extern "C" fn retro_cheat_set( index: $crate::libc::c_uint, is_enabled: bool, code: *const $crate::libc::c_char ) {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| unsafe { retro.on_cheat_set( index, is_enabled, code ) } )
+            });
+        }
+    );
+
+    (@entry retro_load_game, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_load_game" )]
+        pub unsafe extern "C" fn retro_load_game( game: *const $crate::libretro_sys::GameInfo ) -> bool {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| unsafe { retro.on_load_game( game ) } )
+            }).unwrap_or( false )
+        }
+    );
+
+    (@entry retro_load_game_special, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_load_game_special" )]
+        pub unsafe extern "C" fn retro_load_game_special( game_type: $crate::libc::c_uint, info: *const $crate::libretro_sys::GameInfo, num_info: $crate::libc::size_t ) -> bool {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| unsafe { retro.on_load_game_special( game_type, info, num_info ) } )
+            }).unwrap_or( false )
+        }
+    );
+
+    (@entry retro_unload_game, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_unload_game" )]
+        pub unsafe extern "C" fn retro_unload_game() {
+            let _ = $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_unload_game() )
+            });
+        }
+    );
+
+    (@entry retro_get_region, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_get_region" )]
+        pub unsafe extern "C" fn retro_get_region() -> $crate::libc::c_uint {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_get_region() )
+            }).unwrap_or( 0 )
+        }
+    );
+
+    (@entry retro_get_memory_data, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_get_memory_data" )]
+        pub unsafe extern "C" fn retro_get_memory_data( id: $crate::libc::c_uint ) -> *mut $crate::libc::c_void {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_get_memory_data( id ) )
+            }).unwrap_or( 0 as *mut _ )
+        }
+    );
+
+    (@entry retro_get_memory_size, $core: path, prefix = $prefix: expr) => (
+        #[doc(hidden)]
+        #[export_name = concat!( $prefix, "retro_get_memory_size" )]
+        pub unsafe extern "C" fn retro_get_memory_size( id: $crate::libc::c_uint ) -> $crate::libc::size_t {
+            $crate::catch_panic( || {
+                LIBRETRO_INSTANCE.with( |retro| retro.on_get_memory_size( id ) )
+            }).unwrap_or( 0 )
+        }
+    );
+}
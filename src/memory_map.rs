@@ -0,0 +1,184 @@
+use std::ffi::CString;
+use std::ptr;
+
+use libc;
+use libretro_sys;
+
+use environment::{call_environment, EnvError};
+use interfaces;
+use lock;
+
+const ENVIRONMENT_SET_MEMORY_MAPS: libc::c_uint = 36 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+
+const MEMDESC_CONST: u64 = 1 << 0;
+const MEMDESC_BIGENDIAN: u64 = 1 << 1;
+const MEMDESC_SYSTEM_RAM: u64 = 1 << 2;
+const MEMDESC_SAVE_RAM: u64 = 1 << 3;
+const MEMDESC_VIDEO_RAM: u64 = 1 << 4;
+
+#[repr(C)]
+struct RawMemoryDescriptor {
+    flags: u64,
+    pointer: *mut libc::c_void,
+    offset: libc::size_t,
+    start: libc::size_t,
+    select: libc::size_t,
+    disconnect: libc::size_t,
+    length: libc::size_t,
+    address_space: *const libc::c_char
+}
+
+#[repr(C)]
+struct RawMemoryMap {
+    descriptors: *const RawMemoryDescriptor,
+    count: libc::c_uint
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MemoryKind {
+    Other,
+    SystemRam,
+    SaveRam,
+    VideoRam
+}
+
+// Describes where a block of the core's memory lives in the emulated
+// address space; see `retro_memory_descriptor` in libretro.h for how
+// `select` and `disconnect` are interpreted.
+pub struct MemoryDescriptor {
+    pointer: *mut u8,
+    offset: usize,
+    start: usize,
+    select: usize,
+    disconnect: usize,
+    length: usize,
+    kind: MemoryKind,
+    is_read_only: bool,
+    is_big_endian: bool,
+    address_space: Option< CString >
+}
+
+impl MemoryDescriptor {
+    // The frontend reads and writes through `pointer` (e.g. for cheats and
+    // achievements) behind the core's back, so it has to be valid for reads
+    // and writes of `offset + length` bytes, with the `offset` given later,
+    // and stay where it is for as long as the game is loaded.
+    pub unsafe fn new( pointer: *mut u8, start: usize, length: usize ) -> MemoryDescriptor {
+        MemoryDescriptor {
+            pointer: pointer,
+            offset: 0,
+            start: start,
+            select: 0,
+            disconnect: 0,
+            length: length,
+            kind: MemoryKind::Other,
+            is_read_only: false,
+            is_big_endian: false,
+            address_space: None
+        }
+    }
+
+    pub fn offset( mut self, offset: usize ) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn select( mut self, select: usize ) -> Self {
+        self.select = select;
+        self
+    }
+
+    pub fn disconnect( mut self, disconnect: usize ) -> Self {
+        self.disconnect = disconnect;
+        self
+    }
+
+    pub fn kind( mut self, kind: MemoryKind ) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn read_only( mut self ) -> Self {
+        self.is_read_only = true;
+        self
+    }
+
+    pub fn big_endian( mut self ) -> Self {
+        self.is_big_endian = true;
+        self
+    }
+
+    pub fn address_space( mut self, name: &str ) -> Self {
+        self.address_space = Some( CString::new( name ).unwrap() );
+        self
+    }
+
+    fn raw( &self ) -> RawMemoryDescriptor {
+        let mut flags = match self.kind {
+            MemoryKind::Other => 0,
+            MemoryKind::SystemRam => MEMDESC_SYSTEM_RAM,
+            MemoryKind::SaveRam => MEMDESC_SAVE_RAM,
+            MemoryKind::VideoRam => MEMDESC_VIDEO_RAM
+        };
+
+        if self.is_read_only {
+            flags |= MEMDESC_CONST;
+        }
+
+        if self.is_big_endian {
+            flags |= MEMDESC_BIGENDIAN;
+        }
+
+        RawMemoryDescriptor {
+            flags: flags,
+            pointer: self.pointer as *mut libc::c_void,
+            offset: self.offset,
+            start: self.start,
+            select: self.select,
+            disconnect: self.disconnect,
+            length: self.length,
+            address_space: self.address_space.as_ref().map( |name| name.as_ptr() ).unwrap_or( ptr::null() )
+        }
+    }
+}
+
+pub struct MemoryMap {
+    descriptors: Vec< MemoryDescriptor >,
+    raw: Vec< RawMemoryDescriptor >
+}
+
+// The pointers only ever go to the frontend, which
+// can access them from whichever thread it wants anyway.
+unsafe impl Send for MemoryMap {}
+
+impl MemoryMap {
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            descriptors: Vec::new(),
+            raw: Vec::new()
+        }
+    }
+
+    pub fn descriptor( mut self, descriptor: MemoryDescriptor ) -> Self {
+        self.raw.push( descriptor.raw() );
+        self.descriptors.push( descriptor );
+        self
+    }
+
+    // Should be called from `Core::on_load_game`.
+    pub fn register( self ) -> Result< (), EnvError > {
+        let map = RawMemoryMap {
+            descriptors: self.raw.as_ptr(),
+            count: self.raw.len() as libc::c_uint
+        };
+
+        unsafe {
+            call_environment( ENVIRONMENT_SET_MEMORY_MAPS, &map )?;
+        }
+
+        // The frontend keeps pointers to the address space names.
+        interfaces::with( |interfaces| *lock( &interfaces.memory_map ) = Some( self ) );
+
+        Ok(())
+    }
+}
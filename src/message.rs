@@ -0,0 +1,174 @@
+use std::ffi::CString;
+
+use libc;
+use libretro_sys;
+
+use environment::{self, call_environment, call_environment_mut, EnvError};
+
+const ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION: libc::c_uint = 59;
+const ENVIRONMENT_SET_MESSAGE_EXT: libc::c_uint = 60;
+
+// Used to convert the duration for frontends which only
+// support the old, frame based messages.
+const FALLBACK_FRAMES_PER_SECOND: u32 = 60;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl LogLevel {
+    pub(crate) fn raw( self ) -> libc::c_int {
+        match self {
+            LogLevel::Debug => 0,
+            LogLevel::Info => 1,
+            LogLevel::Warn => 2,
+            LogLevel::Error => 3
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MessageTarget {
+    All,
+    OnScreen,
+    Log
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MessageKind {
+    Notification,
+    NotificationAlternative,
+    Status,
+    Progress
+}
+
+#[repr(C)]
+struct RawMessageExt {
+    msg: *const libc::c_char,
+    duration: libc::c_uint,
+    priority: libc::c_uint,
+    level: libc::c_int,
+    target: libc::c_int,
+    kind: libc::c_int,
+    progress: i8
+}
+
+pub struct Message {
+    text: CString,
+    duration: u32,
+    priority: u32,
+    level: LogLevel,
+    target: MessageTarget,
+    kind: MessageKind,
+    progress: Option< u8 >
+}
+
+impl Message {
+    // Any NUL bytes in the text are left out.
+    pub fn new( text: &str ) -> Message {
+        let text = match CString::new( text ) {
+            Ok( text ) => text,
+            Err( _ ) => CString::new( text.replace( '\0', "" ) ).unwrap()
+        };
+
+        Message {
+            text: text,
+            duration: 3000,
+            priority: 1,
+            level: LogLevel::Info,
+            target: MessageTarget::All,
+            kind: MessageKind::Notification,
+            progress: None
+        }
+    }
+
+    // In milliseconds.
+    pub fn duration( mut self, duration: u32 ) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn priority( mut self, priority: u32 ) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn level( mut self, level: LogLevel ) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn target( mut self, target: MessageTarget ) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn kind( mut self, kind: MessageKind ) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    // In percent; only shown for `MessageKind::Progress`.
+    pub fn progress( mut self, progress: u8 ) -> Self {
+        self.progress = Some( if progress > 100 { 100 } else { progress } );
+        self
+    }
+
+    pub fn show( &self ) -> Result< (), EnvError > {
+        let mut version: libc::c_uint = 0;
+        let is_supported = unsafe {
+            call_environment_mut( ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION, &mut version ).is_ok()
+        };
+
+        if is_supported && version >= 1 {
+            let message = RawMessageExt {
+                msg: self.text.as_ptr(),
+                duration: self.duration as libc::c_uint,
+                priority: self.priority as libc::c_uint,
+                level: self.level.raw(),
+                target: match self.target {
+                    MessageTarget::All => 0,
+                    MessageTarget::OnScreen => 1,
+                    MessageTarget::Log => 2
+                },
+                kind: match self.kind {
+                    MessageKind::Notification => 0,
+                    MessageKind::NotificationAlternative => 1,
+                    MessageKind::Status => 2,
+                    MessageKind::Progress => 3
+                },
+                progress: self.progress.map( |progress| progress as i8 ).unwrap_or( -1 )
+            };
+
+            return environment::known_supported( unsafe { call_environment( ENVIRONMENT_SET_MESSAGE_EXT, &message ) } );
+        }
+
+        if self.target == MessageTarget::Log {
+            return Err( EnvError::Unsupported );
+        }
+
+        let message = libretro_sys::Message {
+            msg: self.text.as_ptr(),
+            frames: (self.duration.saturating_mul( FALLBACK_FRAMES_PER_SECOND ) / 1000) as libc::c_uint
+        };
+
+        unsafe {
+            call_environment( libretro_sys::ENVIRONMENT_SET_MESSAGE, &message )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_bytes_are_left_out() {
+        assert_eq!( Message::new( "a\0b\0" ).text.as_bytes(), b"ab" );
+        assert_eq!( Message::new( "ab" ).text.as_bytes(), b"ab" );
+    }
+}
@@ -0,0 +1,90 @@
+use libc;
+use libretro_sys;
+
+use environment::call_environment_mut;
+
+const ENVIRONMENT_GET_MIDI_INTERFACE: libc::c_uint = 48 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RawMidiInterface {
+    input_enabled: Option< unsafe extern "C" fn() -> bool >,
+    output_enabled: Option< unsafe extern "C" fn() -> bool >,
+    read: Option< unsafe extern "C" fn( byte: *mut u8 ) -> bool >,
+    write: Option< unsafe extern "C" fn( byte: u8, delta_time: u32 ) -> bool >,
+    flush: Option< unsafe extern "C" fn() -> bool >
+}
+
+#[derive(Copy, Clone)]
+pub struct Midi {
+    interface: RawMidiInterface
+}
+
+impl Midi {
+    pub(crate) fn query() -> Option< Midi > {
+        let mut interface = RawMidiInterface {
+            input_enabled: None,
+            output_enabled: None,
+            read: None,
+            write: None,
+            flush: None
+        };
+
+        unsafe {
+            if call_environment_mut( ENVIRONMENT_GET_MIDI_INTERFACE, &mut interface ).is_err() {
+                return None;
+            }
+        }
+
+        if interface.input_enabled.is_none() ||
+           interface.output_enabled.is_none() ||
+           interface.read.is_none() ||
+           interface.write.is_none() ||
+           interface.flush.is_none() {
+            return None;
+        }
+
+        Some( Midi {
+            interface: interface
+        })
+    }
+
+    pub fn is_input_enabled( &self ) -> bool {
+        unsafe {
+            (self.interface.input_enabled.unwrap())()
+        }
+    }
+
+    pub fn is_output_enabled( &self ) -> bool {
+        unsafe {
+            (self.interface.output_enabled.unwrap())()
+        }
+    }
+
+    pub fn read( &self ) -> Option< u8 > {
+        let mut byte = 0;
+        let ok = unsafe {
+            (self.interface.read.unwrap())( &mut byte )
+        };
+
+        if ok {
+            Some( byte )
+        } else {
+            None
+        }
+    }
+
+    // The delta time is the number of microseconds
+    // which have passed since the last write.
+    pub fn write( &self, byte: u8, delta_time: u32 ) -> bool {
+        unsafe {
+            (self.interface.write.unwrap())( byte, delta_time )
+        }
+    }
+
+    pub fn flush( &self ) -> bool {
+        unsafe {
+            (self.interface.flush.unwrap())()
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::ptr;
+use std::slice;
+
+use libc;
+
+use environment::call_environment;
+use {Core, Retro};
+
+const ENVIRONMENT_SET_NETPACKET_INTERFACE: libc::c_uint = 78;
+
+const NETPACKET_RELIABLE: libc::c_int = 1 << 0;
+const NETPACKET_UNSEQUENCED: libc::c_int = 1 << 1;
+const NETPACKET_FLUSH_HINT: libc::c_int = 1 << 2;
+
+// Client 0 is always the host.
+pub const HOST_CLIENT_ID: u16 = 0;
+pub const BROADCAST_CLIENT_ID: u16 = 0xFFFF;
+
+type SendFn = unsafe extern "C" fn( flags: libc::c_int, buffer: *const libc::c_void, length: libc::size_t, client_id: u16 );
+type PollReceiveFn = unsafe extern "C" fn();
+
+#[repr(C)]
+struct RawNetpacketCallback {
+    start: unsafe extern "C" fn( client_id: u16, send: SendFn, poll_receive: PollReceiveFn ),
+    receive: unsafe extern "C" fn( buffer: *const libc::c_void, length: libc::size_t, client_id: u16 ),
+    stop: unsafe extern "C" fn(),
+    poll: unsafe extern "C" fn(),
+    connected: unsafe extern "C" fn( client_id: u16 ) -> bool,
+    disconnected: unsafe extern "C" fn( client_id: u16 ),
+    protocol_version: *const libc::c_char
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct PacketFlags {
+    is_reliable: bool,
+    is_unsequenced: bool,
+    flush: bool
+}
+
+impl PacketFlags {
+    pub fn new() -> PacketFlags {
+        PacketFlags::default()
+    }
+
+    // Resent until it arrives.
+    pub fn reliable( mut self ) -> Self {
+        self.is_reliable = true;
+        self
+    }
+
+    // Can arrive out of order relative to other packets.
+    pub fn unsequenced( mut self ) -> Self {
+        self.is_unsequenced = true;
+        self
+    }
+
+    // Hints the frontend to send out everything queued so far right away.
+    pub fn flush( mut self ) -> Self {
+        self.flush = true;
+        self
+    }
+
+    fn raw( self ) -> libc::c_int {
+        let mut flags = 0;
+        if self.is_reliable {
+            flags |= NETPACKET_RELIABLE;
+        }
+
+        if self.is_unsequenced {
+            flags |= NETPACKET_UNSEQUENCED;
+        }
+
+        if self.flush {
+            flags |= NETPACKET_FLUSH_HINT;
+        }
+
+        flags
+    }
+}
+
+// Handed to `Core::on_netpacket_start`; only valid until `Core::on_netpacket_stop`.
+#[derive(Copy, Clone)]
+pub struct Netpacket {
+    client_id: u16,
+    send: SendFn,
+    poll_receive: PollReceiveFn
+}
+
+impl Netpacket {
+    // Our own client ID; `HOST_CLIENT_ID` if we're the host.
+    pub fn client_id( &self ) -> u16 {
+        self.client_id
+    }
+
+    pub fn is_host( &self ) -> bool {
+        self.client_id == HOST_CLIENT_ID
+    }
+
+    // Clients can only send to the host; the host can
+    // also use `BROADCAST_CLIENT_ID` to send to everyone.
+    pub fn send( &self, flags: PacketFlags, data: &[u8], client_id: u16 ) {
+        unsafe {
+            (self.send)( flags.raw(), data.as_ptr() as *const libc::c_void, data.len(), client_id );
+        }
+    }
+
+    // Hands any packets which are already waiting to `callback` right away
+    // instead of to `Core::on_netpacket_receive`, since the core is usually
+    // still in the middle of one of its own callbacks at this point.
+    pub fn poll_receive< F: FnMut( &[u8], u16 ) >( &self, mut callback: F ) {
+        let previous = POLLED_PACKETS.with( |packets| packets.replace( Some( Vec::new() ) ) );
+        unsafe {
+            (self.poll_receive)();
+        }
+
+        let packets = POLLED_PACKETS.with( |packets| packets.replace( previous ) ).unwrap_or_default();
+        for (data, client_id) in packets {
+            callback( &data, client_id );
+        }
+    }
+}
+
+thread_local! {
+    // Set while inside of `Netpacket::poll_receive`, during which the frontend
+    // calls `receive` synchronously; the core can't be borrowed again then.
+    static POLLED_PACKETS: RefCell< Option< Vec< (Vec< u8 >, u16) > > > = RefCell::new( None );
+}
+
+unsafe fn with_core< B: Core, R, F: FnOnce( &mut B ) -> R >( default: R, callback: F ) -> R {
+    match Retro::< B >::active() {
+        Some( retro ) => ::catch_panic( || callback( &mut retro.core ) ).unwrap_or( default ),
+        None => default
+    }
+}
+
+unsafe extern "C" fn start< B: Core >( client_id: u16, send: SendFn, poll_receive: PollReceiveFn ) {
+    let netpacket = Netpacket {
+        client_id: client_id,
+        send: send,
+        poll_receive: poll_receive
+    };
+
+    with_core::< B, _, _ >( (), |core| core.on_netpacket_start( netpacket ) )
+}
+
+unsafe extern "C" fn receive< B: Core >( buffer: *const libc::c_void, length: libc::size_t, client_id: u16 ) {
+    let data = if buffer == ptr::null() || length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts( buffer as *const u8, length )
+    };
+
+    let is_polling = POLLED_PACKETS.with( |packets| {
+        match *packets.borrow_mut() {
+            Some( ref mut packets ) => {
+                packets.push( (data.to_vec(), client_id) );
+                true
+            },
+            None => false
+        }
+    });
+
+    if is_polling {
+        return;
+    }
+
+    with_core::< B, _, _ >( (), |core| core.on_netpacket_receive( data, client_id ) )
+}
+
+unsafe extern "C" fn stop< B: Core >() {
+    with_core::< B, _, _ >( (), |core| core.on_netpacket_stop() )
+}
+
+unsafe extern "C" fn poll< B: Core >() {
+    with_core::< B, _, _ >( (), |core| core.on_netpacket_poll() )
+}
+
+unsafe extern "C" fn connected< B: Core >( client_id: u16 ) -> bool {
+    with_core::< B, _, _ >( false, |core| core.on_netpacket_connected( client_id ) )
+}
+
+unsafe extern "C" fn disconnected< B: Core >( client_id: u16 ) {
+    with_core::< B, _, _ >( (), |core| core.on_netpacket_disconnected( client_id ) )
+}
+
+pub(crate) fn register< B: Core >( protocol_version: *const libc::c_char ) {
+    let callback = RawNetpacketCallback {
+        start: start::< B >,
+        receive: receive::< B >,
+        stop: stop::< B >,
+        poll: poll::< B >,
+        connected: connected::< B >,
+        disconnected: disconnected::< B >,
+        protocol_version: protocol_version
+    };
+
+    unsafe {
+        let _ = call_environment( ENVIRONMENT_SET_NETPACKET_INTERFACE, &callback );
+    }
+}
@@ -0,0 +1,527 @@
+use std::error;
+use std::fmt;
+use std::ptr;
+use std::str::FromStr;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libc;
+use libretro_sys;
+
+use environment::{self, call_environment, call_environment_mut, EnvError};
+use language::Language;
+use {Core, Retro};
+
+const ENVIRONMENT_GET_CORE_OPTIONS_VERSION: libc::c_uint = 52;
+const ENVIRONMENT_SET_CORE_OPTIONS_INTL: libc::c_uint = 54;
+const NUM_CORE_OPTION_VALUES_MAX: usize = 128;
+// One slot is needed for the terminator.
+const MAX_VALUE_COUNT: usize = NUM_CORE_OPTION_VALUES_MAX - 1;
+
+const ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY: libc::c_uint = 55;
+const ENVIRONMENT_SET_CORE_OPTIONS_UPDATE_DISPLAY_CALLBACK: libc::c_uint = 69;
+
+#[repr(C)]
+struct RawCoreOptionDisplay {
+    key: *const libc::c_char,
+    visible: bool
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RawCoreOptionValue {
+    value: *const libc::c_char,
+    label: *const libc::c_char
+}
+
+#[repr(C)]
+struct RawCoreOptionDefinition {
+    key: *const libc::c_char,
+    desc: *const libc::c_char,
+    info: *const libc::c_char,
+    values: [RawCoreOptionValue; NUM_CORE_OPTION_VALUES_MAX],
+    default_value: *const libc::c_char
+}
+
+#[repr(C)]
+struct RawCoreOptionsIntl {
+    us: *const RawCoreOptionDefinition,
+    local: *const RawCoreOptionDefinition
+}
+
+#[repr(C)]
+struct RawUpdateDisplayCallback {
+    callback: unsafe extern "C" fn() -> bool
+}
+
+struct Translation {
+    language: Language,
+    description: Option< String >,
+    value_labels: Vec< (String, String) >
+}
+
+pub struct CoreOption {
+    key: String,
+    description: String,
+    values: Vec< String >,
+    translations: Vec< Translation >
+}
+
+impl CoreOption {
+    pub fn new( key: &str, description: &str ) -> CoreOption {
+        CoreOption {
+            key: key.to_owned(),
+            description: description.to_owned(),
+            values: Vec::new(),
+            translations: Vec::new()
+        }
+    }
+
+    fn translation_mut( &mut self, language: Language ) -> &mut Translation {
+        let index = match self.translations.iter().position( |translation| translation.language == language ) {
+            Some( index ) => index,
+            None => {
+                self.translations.push( Translation {
+                    language: language,
+                    description: None,
+                    value_labels: Vec::new()
+                });
+                self.translations.len() - 1
+            }
+        };
+
+        &mut self.translations[ index ]
+    }
+
+    // Shown instead of the description when the frontend is set to `language`.
+    pub fn translation( mut self, language: Language, description: &str ) -> Self {
+        self.translation_mut( language ).description = Some( description.to_owned() );
+        self
+    }
+
+    // Shown instead of `value` itself; the core still gets the untranslated value.
+    pub fn value_translation( mut self, language: Language, value: &str, label: &str ) -> Self {
+        self.translation_mut( language ).value_labels.push( (value.to_owned(), label.to_owned()) );
+        self
+    }
+
+    // The first value is the one the frontend picks by default.
+    pub fn value( mut self, value: &str ) -> Self {
+        self.values.push( value.to_owned() );
+        self
+    }
+
+    pub fn values( mut self, values: &[&str] ) -> Self {
+        self.values.extend( values.iter().map( |value| (*value).to_owned() ) );
+        self
+    }
+
+    // Adds every value from `min` to `max` (inclusive) in increments of `step`;
+    // read it back with `get_i64` or `get_as`. The default has to be one of
+    // those values, and there can't be more of them than the frontend supports.
+    pub fn range( mut self, min: i64, max: i64, step: i64, default: i64 ) -> Result< Self, OptionError > {
+        let invalid = |key: &str, reason: &'static str| Err( OptionError::InvalidRange { key: key.to_owned(), reason: reason } );
+        if step <= 0 {
+            return invalid( &self.key, "the step has to be positive" );
+        }
+
+        if min > max {
+            return invalid( &self.key, "the minimum is bigger than the maximum" );
+        }
+
+        if default < min || default > max || (default as i128 - min as i128) % step as i128 != 0 {
+            return invalid( &self.key, "the default is not one of the values" );
+        }
+
+        // Done in 128 bits since the values can span the whole range of an `i64`.
+        let count = (max as i128 - min as i128) / step as i128 + 1;
+        if count > MAX_VALUE_COUNT as i128 {
+            return invalid( &self.key, "there are too many values" );
+        }
+
+        for index in 0..count {
+            self.values.push( (min as i128 + index * step as i128).to_string() );
+        }
+
+        Ok( self.default_value( &default.to_string() ) )
+    }
+
+    pub fn default_value( mut self, value: &str ) -> Self {
+        self.values.retain( |existing| existing != value );
+        self.values.insert( 0, value.to_owned() );
+        self
+    }
+
+    pub fn key( &self ) -> &str {
+        &self.key
+    }
+
+    pub fn description( &self ) -> &str {
+        &self.description
+    }
+
+    pub fn possible_values( &self ) -> &[String] {
+        &self.values
+    }
+
+    fn to_variable_value( &self ) -> CString {
+        let mut value = self.description.clone();
+        value.push_str( "; " );
+        value.push_str( &self.values.join( "|" ) );
+        CString::new( value ).unwrap()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum OptionError {
+    // The frontend doesn't have a value for the option.
+    NotSet( String ),
+    // The value couldn't be parsed into the requested type.
+    InvalidValue { key: String, value: String },
+    // The values given to `CoreOption::range` don't make sense.
+    InvalidRange { key: String, reason: &'static str }
+}
+
+impl fmt::Display for OptionError {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        match *self {
+            OptionError::NotSet( ref key ) => write!( formatter, "option '{}' is not set", key ),
+            OptionError::InvalidValue { ref key, ref value } => write!( formatter, "option '{}' has an invalid value: '{}'", key, value ),
+            OptionError::InvalidRange { ref key, reason } => write!( formatter, "option '{}' has an invalid range: {}", key, reason )
+        }
+    }
+}
+
+impl error::Error for OptionError {}
+
+pub trait CoreOptions {
+    fn options() -> Vec< CoreOption >;
+    fn load_from_frontend( &mut self );
+
+    // Cheap enough to be called every frame; returns whether anything was reloaded.
+    fn update_from_frontend( &mut self ) -> bool {
+        if has_changed() == false {
+            return false;
+        }
+
+        self.load_from_frontend();
+        true
+    }
+}
+
+pub trait FromOptionValue: Sized {
+    fn from_option_value( value: &str ) -> Option< Self >;
+}
+
+impl FromOptionValue for bool {
+    fn from_option_value( value: &str ) -> Option< Self > {
+        match value {
+            "enabled" | "true" | "on" | "yes" => Some( true ),
+            "disabled" | "false" | "off" | "no" => Some( false ),
+            _ => None
+        }
+    }
+}
+
+impl FromOptionValue for String {
+    fn from_option_value( value: &str ) -> Option< Self > {
+        Some( value.to_owned() )
+    }
+}
+
+macro_rules! impl_from_option_value {
+    ($($kind: ty),+) => {
+        $(
+            impl FromOptionValue for $kind {
+                fn from_option_value( value: &str ) -> Option< Self > {
+                    value.trim().parse().ok()
+                }
+            }
+        )+
+    }
+}
+
+impl_from_option_value!( u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64 );
+
+fn empty_definition() -> RawCoreOptionDefinition {
+    RawCoreOptionDefinition {
+        key: ptr::null(),
+        desc: ptr::null(),
+        info: ptr::null(),
+        values: [RawCoreOptionValue { value: ptr::null(), label: ptr::null() }; NUM_CORE_OPTION_VALUES_MAX],
+        default_value: ptr::null()
+    }
+}
+
+// The translated strings can only be passed through the newer options API,
+// so this is only used when there's actually something to translate.
+fn register_translated( options: &[CoreOption] ) -> bool {
+    if options.iter().all( |option| option.translations.is_empty() ) {
+        return false;
+    }
+
+    if options.iter().any( |option| option.values.len() > MAX_VALUE_COUNT ) {
+        return false;
+    }
+
+    let mut version: libc::c_uint = 0;
+    unsafe {
+        if call_environment_mut( ENVIRONMENT_GET_CORE_OPTIONS_VERSION, &mut version ).is_err() || version < 1 {
+            return false;
+        }
+    }
+
+    let language = environment::get_language().unwrap_or( Language::English );
+
+    // The pointers stay valid when this gets reallocated since
+    // they point into each string's own heap allocation.
+    let mut strings: Vec< CString > = Vec::new();
+    let mut intern = |string: &str| {
+        let string = CString::new( string ).unwrap();
+        let pointer = string.as_ptr();
+        strings.push( string );
+        pointer
+    };
+
+    let mut us = Vec::new();
+    let mut local = Vec::new();
+    for option in options {
+        let mut definition = empty_definition();
+        definition.key = intern( &option.key );
+        definition.desc = intern( &option.description );
+        for (output, value) in definition.values.iter_mut().zip( option.values.iter() ) {
+            output.value = intern( value );
+        }
+        definition.default_value = definition.values[ 0 ].value;
+        us.push( definition );
+
+        let translation = match option.translations.iter().find( |translation| translation.language == language ) {
+            Some( translation ) => translation,
+            None => continue
+        };
+
+        let mut definition = empty_definition();
+        definition.key = intern( &option.key );
+        if let Some( ref description ) = translation.description {
+            definition.desc = intern( description );
+        }
+        for (output, &(ref value, ref label)) in definition.values.iter_mut().zip( translation.value_labels.iter() ) {
+            output.value = intern( value );
+            output.label = intern( label );
+        }
+        local.push( definition );
+    }
+
+    us.push( empty_definition() );
+    local.push( empty_definition() );
+
+    let options_intl = RawCoreOptionsIntl {
+        us: us.as_ptr(),
+        local: if local.len() > 1 { local.as_ptr() } else { ptr::null() }
+    };
+
+    unsafe {
+        call_environment( ENVIRONMENT_SET_CORE_OPTIONS_INTL, &options_intl ).is_ok()
+    }
+}
+
+pub(crate) fn register( options: &[CoreOption] ) {
+    if register_translated( options ) {
+        return;
+    }
+
+    let keys: Vec< CString > = options.iter().map( |option| CString::new( option.key.as_str() ).unwrap() ).collect();
+    let values: Vec< CString > = options.iter().map( |option| option.to_variable_value() ).collect();
+
+    let mut variables: Vec< libretro_sys::Variable > = keys.iter().zip( values.iter() ).map( |(key, value)| {
+        libretro_sys::Variable {
+            key: key.as_ptr(),
+            value: value.as_ptr()
+        }
+    }).collect();
+
+    variables.push( libretro_sys::Variable {
+        key: ptr::null(),
+        value: ptr::null()
+    });
+
+    unsafe {
+        let _ = call_environment( libretro_sys::ENVIRONMENT_SET_VARIABLES, &variables[ 0 ] );
+    }
+}
+
+pub fn get( key: &str ) -> Option< String > {
+    let key = match CString::new( key ) {
+        Ok( key ) => key,
+        Err( _ ) => return None
+    };
+
+    let mut variable = libretro_sys::Variable {
+        key: key.as_ptr(),
+        value: ptr::null()
+    };
+
+    unsafe {
+        if call_environment_mut( libretro_sys::ENVIRONMENT_GET_VARIABLE, &mut variable ).is_err() || variable.value == ptr::null() {
+            return None;
+        }
+
+        CStr::from_ptr( variable.value as *const libc::c_char ).to_str().ok().map( |value| value.to_owned() )
+    }
+}
+
+fn get_parsed< T, F: FnOnce( &str ) -> Option< T > >( key: &str, parse: F ) -> Result< T, OptionError > {
+    let value = get( key ).ok_or_else( || OptionError::NotSet( key.to_owned() ) )?;
+    parse( &value ).ok_or_else( || OptionError::InvalidValue { key: key.to_owned(), value: value } )
+}
+
+pub fn get_bool( key: &str ) -> Result< bool, OptionError > {
+    get_parsed( key, bool::from_option_value )
+}
+
+pub fn get_i64( key: &str ) -> Result< i64, OptionError > {
+    get_parsed( key, i64::from_option_value )
+}
+
+pub fn get_f64( key: &str ) -> Result< f64, OptionError > {
+    get_parsed( key, f64::from_option_value )
+}
+
+pub fn get_enum< T: FromStr >( key: &str ) -> Result< T, OptionError > {
+    get_parsed( key, |value| value.parse().ok() )
+}
+
+// The frontend's flag is cleared when it's read, so whenever we read it
+// ourselves we have to remember it for the core's own `has_changed` calls.
+static IS_CHANGE_PENDING: AtomicBool = AtomicBool::new( false );
+
+fn query_changes() -> bool {
+    let mut has_changed = false;
+    unsafe {
+        if call_environment_mut( libretro_sys::ENVIRONMENT_GET_VARIABLE_UPDATE, &mut has_changed ).is_err() {
+            return false;
+        }
+    }
+
+    has_changed
+}
+
+pub(crate) fn poll_changes() -> bool {
+    let has_changed = query_changes();
+    if has_changed {
+        IS_CHANGE_PENDING.store( true, Ordering::Relaxed );
+    }
+
+    has_changed
+}
+
+// Whether any of the options were changed since the last time this was called.
+pub fn has_changed() -> bool {
+    let is_change_pending = IS_CHANGE_PENDING.swap( false, Ordering::Relaxed );
+    query_changes() || is_change_pending
+}
+
+pub fn get_as< T: FromOptionValue >( key: &str ) -> Option< T > {
+    get( key ).and_then( |value| T::from_option_value( &value ) )
+}
+
+// Hides or shows an option in the frontend's menu; the option keeps its value either way.
+pub fn set_visible( key: &str, is_visible: bool ) -> Result< (), EnvError > {
+    let key = match CString::new( key ) {
+        Ok( key ) => key,
+        Err( _ ) => return Err( EnvError::InvalidArgument )
+    };
+
+    let display = RawCoreOptionDisplay {
+        key: key.as_ptr(),
+        visible: is_visible
+    };
+
+    unsafe {
+        call_environment( ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY, &display )
+    }
+}
+
+unsafe extern "C" fn update_display< B: Core >() -> bool {
+    match Retro::< B >::active() {
+        Some( retro ) => ::catch_panic( || retro.core.on_update_option_visibility() ).unwrap_or( false ),
+        None => false
+    }
+}
+
+// Lets the frontend ask for `Core::on_update_option_visibility`
+// to be called whenever it's about to show the options.
+pub(crate) fn register_update_display_callback< B: Core >() {
+    let callback = RawUpdateDisplayCallback {
+        callback: update_display::< B >
+    };
+
+    unsafe {
+        let _ = call_environment( ENVIRONMENT_SET_CORE_OPTIONS_UPDATE_DISPLAY_CALLBACK, &callback );
+    }
+}
+
+impl< B: Core > Retro< B > {
+    pub(crate) fn read_option_values() -> Vec< Option< String > > {
+        Self::core_info().options.iter().map( |option| get( option.key() ) ).collect()
+    }
+
+    pub(crate) fn dispatch_option_changes( &mut self ) {
+        let core_info = Self::core_info();
+        if core_info.options.is_empty() || poll_changes() == false {
+            return;
+        }
+
+        let values = Self::read_option_values();
+        let changed_keys: Vec< &str > = core_info.options.iter().zip( values.iter().zip( self.option_values.iter() ) )
+            .filter( |&(_, (new_value, old_value))| new_value != old_value )
+            .map( |(option, _)| option.key() )
+            .collect();
+
+        self.option_values = values;
+        if changed_keys.is_empty() == false {
+            self.core.on_options_changed( &changed_keys );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_puts_the_default_first() {
+        let option = CoreOption::new( "key", "Description" ).range( 0, 10, 5, 5 ).unwrap();
+        assert_eq!( option.possible_values(), &["5", "0", "10"] );
+    }
+
+    #[test]
+    fn range_stops_at_the_last_step_before_max() {
+        let option = CoreOption::new( "key", "Description" ).range( 1, 8, 3, 1 ).unwrap();
+        assert_eq!( option.possible_values(), &["1", "4", "7"] );
+    }
+
+    #[test]
+    fn range_near_the_limits_does_not_overflow() {
+        let option = CoreOption::new( "key", "Description" ).range( i64::MAX - 2, i64::MAX, 2, i64::MAX - 2 ).unwrap();
+        assert_eq!( option.possible_values(), &[(i64::MAX - 2).to_string(), i64::MAX.to_string()] );
+
+        let option = CoreOption::new( "key", "Description" ).range( i64::MIN, i64::MAX, i64::MAX, 0 );
+        assert!( option.is_err() );
+
+        let option = CoreOption::new( "key", "Description" ).range( i64::MIN, i64::MAX, i64::MAX, i64::MIN ).unwrap();
+        assert_eq!( option.possible_values().len(), 3 );
+    }
+
+    #[test]
+    fn range_rejects_invalid_arguments() {
+        let range = |min, max, step, default| CoreOption::new( "key", "Description" ).range( min, max, step, default ).is_err();
+        assert!( range( 0, 10, 0, 0 ) );
+        assert!( range( 0, 10, -1, 0 ) );
+        assert!( range( 10, 0, 1, 5 ) );
+        assert!( range( 0, 10, 1, 11 ) );
+        assert!( range( 0, 10, 4, 3 ) );
+        assert!( range( 0, 127, 1, 0 ) );
+        assert!( range( 0, 126, 1, 0 ) == false );
+    }
+}
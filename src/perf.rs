@@ -0,0 +1,156 @@
+use std::cell::UnsafeCell;
+
+use libc;
+use libretro_sys;
+
+use environment::call_environment_mut;
+use interfaces;
+use lock;
+
+#[repr(C)]
+struct RawPerfCounter {
+    ident: *const libc::c_char,
+    start: u64,
+    total: u64,
+    call_count: u64,
+    registered: bool
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct RawPerfInterface {
+    get_time_usec: Option< unsafe extern "C" fn() -> i64 >,
+    get_cpu_features: Option< unsafe extern "C" fn() -> u64 >,
+    get_perf_counter: Option< unsafe extern "C" fn() -> u64 >,
+    perf_register: Option< unsafe extern "C" fn( counter: *mut RawPerfCounter ) >,
+    perf_start: Option< unsafe extern "C" fn( counter: *mut RawPerfCounter ) >,
+    perf_stop: Option< unsafe extern "C" fn( counter: *mut RawPerfCounter ) >,
+    perf_log: Option< unsafe extern "C" fn() >
+}
+
+pub(crate) fn query() {
+    let mut interface = RawPerfInterface {
+        get_time_usec: None,
+        get_cpu_features: None,
+        get_perf_counter: None,
+        perf_register: None,
+        perf_start: None,
+        perf_stop: None,
+        perf_log: None
+    };
+
+    let result = unsafe { call_environment_mut( libretro_sys::ENVIRONMENT_GET_PERF_INTERFACE, &mut interface ) };
+    let interface = match result {
+        Ok(()) => Some( interface ),
+        Err( _ ) => None
+    };
+
+    interfaces::with( |interfaces| *lock( &interfaces.perf ) = interface );
+}
+
+fn interface() -> Option< RawPerfInterface > {
+    interfaces::with( |interfaces| *lock( &interfaces.perf ) )
+}
+
+pub fn time_usec() -> Option< i64 > {
+    let callback = interface()?.get_time_usec?;
+    Some( unsafe { callback() } )
+}
+
+pub fn counter() -> Option< u64 > {
+    let callback = interface()?.get_perf_counter?;
+    Some( unsafe { callback() } )
+}
+
+pub fn cpu_features() -> Option< u64 > {
+    let callback = interface()?.get_cpu_features?;
+    Some( unsafe { callback() } )
+}
+
+// Dumps all of the registered counters to the frontend's log.
+pub fn log() {
+    if let Some( callback ) = interface().and_then( |interface| interface.perf_log ) {
+        unsafe {
+            callback();
+        }
+    }
+}
+
+// The frontend holds on to the counters once they're registered,
+// which is why they have to be statics.
+pub struct PerfCounter {
+    raw: UnsafeCell< RawPerfCounter >
+}
+
+unsafe impl Sync for PerfCounter {}
+
+impl PerfCounter {
+    // The name has to be NUL terminated.
+    pub const fn new( name: &'static str ) -> PerfCounter {
+        let bytes = name.as_bytes();
+        assert!( bytes.len() > 0 && bytes[ bytes.len() - 1 ] == 0, "The counter name has to be NUL terminated!" );
+
+        PerfCounter {
+            raw: UnsafeCell::new( RawPerfCounter {
+                ident: bytes.as_ptr() as *const libc::c_char,
+                start: 0,
+                total: 0,
+                call_count: 0,
+                registered: false
+            })
+        }
+    }
+
+    pub fn start( &'static self ) -> PerfScope {
+        if let Some( interface ) = interface() {
+            unsafe {
+                let raw = self.raw.get();
+                if (*raw).registered == false {
+                    if let Some( perf_register ) = interface.perf_register {
+                        perf_register( raw );
+                    }
+                }
+
+                if let Some( perf_start ) = interface.perf_start {
+                    perf_start( raw );
+                }
+            }
+        }
+
+        PerfScope {
+            counter: self
+        }
+    }
+
+    pub fn total( &self ) -> u64 {
+        unsafe { (*self.raw.get()).total }
+    }
+
+    pub fn call_count( &self ) -> u64 {
+        unsafe { (*self.raw.get()).call_count }
+    }
+}
+
+pub struct PerfScope {
+    counter: &'static PerfCounter
+}
+
+impl Drop for PerfScope {
+    fn drop( &mut self ) {
+        if let Some( perf_stop ) = interface().and_then( |interface| interface.perf_stop ) {
+            unsafe {
+                perf_stop( self.counter.raw.get() );
+            }
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! perf_scope {
+    ($name: expr) => (
+        let _perf_scope = {
+            static COUNTER: $crate::perf::PerfCounter = $crate::perf::PerfCounter::new( concat!( $name, "\0" ) );
+            COUNTER.start()
+        };
+    )
+}
@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+
+const SINC_HALF_WIDTH: usize = 16;
+const SINC_PHASES: usize = 512;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Quality {
+    Linear,
+    Sinc
+}
+
+// Converts interleaved stereo audio from one sample rate to another,
+// e.g. from the native rate of an emulated sound chip to the rate
+// declared in the `AudioVideoInfo`.
+pub struct Resampler {
+    quality: Quality,
+    step: f64,
+    position: f64,
+    history: Vec< [f32; 2] >,
+    kernel: Vec< f32 >,
+    output: Vec< i16 >
+}
+
+fn taps_before( quality: Quality ) -> usize {
+    match quality {
+        Quality::Linear => 0,
+        Quality::Sinc => SINC_HALF_WIDTH - 1
+    }
+}
+
+fn taps_after( quality: Quality ) -> usize {
+    match quality {
+        Quality::Linear => 1,
+        Quality::Sinc => SINC_HALF_WIDTH
+    }
+}
+
+// A Blackman windowed sinc, sampled at `SINC_PHASES + 1` fractional offsets.
+fn build_kernel( cutoff: f64 ) -> Vec< f32 > {
+    let width = SINC_HALF_WIDTH * 2;
+    let mut kernel = Vec::with_capacity( (SINC_PHASES + 1) * width );
+    for phase in 0..SINC_PHASES + 1 {
+        let fraction = phase as f64 / SINC_PHASES as f64;
+        let row: Vec< f64 > = (0..width).map( |tap| {
+            let distance = tap as f64 - (SINC_HALF_WIDTH - 1) as f64 - fraction;
+            let x = distance * cutoff;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let t = distance / SINC_HALF_WIDTH as f64;
+            let window = if t.abs() >= 1.0 {
+                0.0
+            } else {
+                0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos()
+            };
+
+            sinc * window
+        }).collect();
+
+        let sum: f64 = row.iter().sum();
+        kernel.extend( row.iter().map( |value| (value / sum) as f32 ) );
+    }
+
+    kernel
+}
+
+impl Resampler {
+    pub fn new( quality: Quality, input_sample_rate: f64, output_sample_rate: f64 ) -> Resampler {
+        assert!( input_sample_rate > 0.0 && output_sample_rate > 0.0, "Sample rates must be positive!" );
+
+        let kernel = match quality {
+            Quality::Linear => Vec::new(),
+            Quality::Sinc => build_kernel( (output_sample_rate / input_sample_rate).min( 1.0 ) )
+        };
+
+        let before = taps_before( quality );
+        Resampler {
+            quality: quality,
+            step: input_sample_rate / output_sample_rate,
+            position: before as f64,
+            history: vec![ [0.0; 2]; before ],
+            kernel: kernel,
+            output: Vec::new()
+        }
+    }
+
+    pub fn quality( &self ) -> Quality {
+        self.quality
+    }
+
+    // Takes interleaved stereo samples at the input rate and returns
+    // however many samples at the output rate are available so far.
+    pub fn process( &mut self, data: &[i16] ) -> &[i16] {
+        assert!( data.len() % 2 == 0, "Audio data must be in stereo!" );
+
+        self.history.extend( data.chunks( 2 ).map( |frame| [frame[ 0 ] as f32, frame[ 1 ] as f32] ) );
+        self.output.clear();
+
+        let before = taps_before( self.quality );
+        let after = taps_after( self.quality );
+        loop {
+            let index = self.position as usize;
+            if index + after >= self.history.len() {
+                break;
+            }
+
+            let fraction = self.position - index as f64;
+            let frame = match self.quality {
+                Quality::Linear => {
+                    let a = self.history[ index ];
+                    let b = self.history[ index + 1 ];
+                    let fraction = fraction as f32;
+                    [a[ 0 ] + (b[ 0 ] - a[ 0 ]) * fraction, a[ 1 ] + (b[ 1 ] - a[ 1 ]) * fraction]
+                },
+                Quality::Sinc => {
+                    let width = SINC_HALF_WIDTH * 2;
+                    let phase = (fraction * SINC_PHASES as f64).round() as usize;
+                    let kernel = &self.kernel[ phase * width..(phase + 1) * width ];
+                    let frames = &self.history[ index - before..index + after + 1 ];
+                    let mut frame = [0.0; 2];
+                    for (input, &weight) in frames.iter().zip( kernel ) {
+                        frame[ 0 ] += input[ 0 ] * weight;
+                        frame[ 1 ] += input[ 1 ] * weight;
+                    }
+                    frame
+                }
+            };
+
+            self.output.push( frame[ 0 ] as i16 );
+            self.output.push( frame[ 1 ] as i16 );
+            self.position += self.step;
+        }
+
+        let consumed = (self.position as usize).saturating_sub( before ).min( self.history.len() );
+        self.history.drain( ..consumed );
+        self.position -= consumed as f64;
+
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_at_the_same_rate_passes_through() {
+        let mut resampler = Resampler::new( Quality::Linear, 44100.0, 44100.0 );
+        assert_eq!( resampler.process( &[1, -1, 2, -2, 3, -3] ), &[1, -1, 2, -2] );
+        assert_eq!( resampler.process( &[4, -4] ), &[3, -3] );
+    }
+
+    #[test]
+    fn linear_upsampling_interpolates() {
+        let mut resampler = Resampler::new( Quality::Linear, 22050.0, 44100.0 );
+        assert_eq!( resampler.process( &[0, 0, 100, -100, 200, -200] ), &[0, 0, 50, -50, 100, -100, 150, -150] );
+    }
+
+    #[test]
+    fn chunk_size_doesnt_matter() {
+        let input: Vec< i16 > = (0..2000).map( |index| ((index as f64 * 0.05).sin() * 10000.0) as i16 ).collect();
+        for &quality in &[Quality::Linear, Quality::Sinc] {
+            let mut resampler = Resampler::new( quality, 32000.0, 44100.0 );
+            let expected = resampler.process( &input ).to_vec();
+
+            let mut resampler = Resampler::new( quality, 32000.0, 44100.0 );
+            let mut output = Vec::new();
+            for chunk in input.chunks( 46 ) {
+                output.extend_from_slice( resampler.process( chunk ) );
+            }
+
+            assert_eq!( output, expected );
+        }
+    }
+
+    #[test]
+    fn sinc_keeps_the_rate_and_the_level() {
+        let input = vec![ 1000; 2 * 32000 ];
+        let mut resampler = Resampler::new( Quality::Sinc, 32000.0, 48000.0 );
+        let output = resampler.process( &input ).to_vec();
+
+        let frames = output.len() / 2;
+        assert!( frames > 48000 - 2 * SINC_HALF_WIDTH * 2 && frames <= 48000, "{}", frames );
+
+        // The first few frames are still fading in from the silent history.
+        for &sample in &output[ SINC_HALF_WIDTH * 4.. ] {
+            assert!( (sample - 1000).abs() <= 1, "{}", sample );
+        }
+    }
+}
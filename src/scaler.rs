@@ -0,0 +1,180 @@
+use frame::{Frame, Pixel};
+
+// Nearest neighbor integer scaling, e.g. for a "prescale" core option
+// so that the frontend's own filtering has more pixels to work with.
+pub fn scale< P: Pixel >( input: &Frame< P >, factor: u32 ) -> Frame< P > {
+    let mut output = Frame::new( input.width() * factor, input.height() * factor );
+    scale_into( input, &mut output, factor );
+    output
+}
+
+pub fn scale_into< P: Pixel >( input: &Frame< P >, output: &mut Frame< P >, factor: u32 ) {
+    assert!( factor > 0 );
+    assert!( output.width() == input.width() * factor && output.height() == input.height() * factor, "The output frame has the wrong size!" );
+
+    for y in 0..input.height() {
+        let first_row = y * factor;
+        scale_row( input.row( y ), output.row_mut( first_row ), factor );
+        for row in first_row + 1..first_row + factor {
+            let (source, destination) = rows_mut( output, first_row, row );
+            destination.copy_from_slice( source );
+        }
+    }
+}
+
+fn rows_mut< P: Pixel >( frame: &mut Frame< P >, source: u32, destination: u32 ) -> (&[P], &mut [P]) {
+    let pitch = frame.pitch() as usize;
+    let width = frame.width() as usize;
+    let (head, tail) = frame.pixels_mut().split_at_mut( destination as usize * pitch );
+    let source = source as usize * pitch;
+    (&head[ source..source + width ], &mut tail[ ..width ])
+}
+
+fn scale_row< P: Pixel >( input: &[P], output: &mut [P], factor: u32 ) {
+    if factor == 2 && double_row_fast( input, output ) {
+        return;
+    }
+
+    let factor = factor as usize;
+    for (&pixel, output) in input.iter().zip( output.chunks_mut( factor ) ) {
+        for value in output {
+            *value = pixel;
+        }
+    }
+}
+
+// The pixels are plain `Copy` values, so duplicating their bits is fine.
+#[cfg(target_arch = "x86_64")]
+fn double_row_fast< P: Pixel >( input: &[P], output: &mut [P] ) -> bool {
+    use std::mem;
+    use std::slice;
+
+    unsafe {
+        match mem::size_of::< P >() {
+            2 => double_row_u16(
+                slice::from_raw_parts( input.as_ptr() as *const u16, input.len() ),
+                slice::from_raw_parts_mut( output.as_mut_ptr() as *mut u16, output.len() )
+            ),
+            4 => double_row_u32(
+                slice::from_raw_parts( input.as_ptr() as *const u32, input.len() ),
+                slice::from_raw_parts_mut( output.as_mut_ptr() as *mut u32, output.len() )
+            ),
+            _ => return false
+        }
+    }
+
+    true
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn double_row_fast< P: Pixel >( _input: &[P], _output: &mut [P] ) -> bool {
+    false
+}
+
+// SSE2 is always available on x86_64.
+#[cfg(target_arch = "x86_64")]
+unsafe fn double_row_u16( input: &[u16], output: &mut [u16] ) {
+    use std::arch::x86_64::*;
+
+    assert!( output.len() >= input.len() * 2 );
+    let chunks = input.len() / 8;
+    for index in 0..chunks {
+        let value = _mm_loadu_si128( input.as_ptr().add( index * 8 ) as *const __m128i );
+        let low = _mm_unpacklo_epi16( value, value );
+        let high = _mm_unpackhi_epi16( value, value );
+        _mm_storeu_si128( output.as_mut_ptr().add( index * 16 ) as *mut __m128i, low );
+        _mm_storeu_si128( output.as_mut_ptr().add( index * 16 + 8 ) as *mut __m128i, high );
+    }
+
+    for index in chunks * 8..input.len() {
+        output[ index * 2 ] = input[ index ];
+        output[ index * 2 + 1 ] = input[ index ];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn double_row_u32( input: &[u32], output: &mut [u32] ) {
+    use std::arch::x86_64::*;
+
+    assert!( output.len() >= input.len() * 2 );
+    let chunks = input.len() / 4;
+    for index in 0..chunks {
+        let value = _mm_loadu_si128( input.as_ptr().add( index * 4 ) as *const __m128i );
+        let low = _mm_unpacklo_epi32( value, value );
+        let high = _mm_unpackhi_epi32( value, value );
+        _mm_storeu_si128( output.as_mut_ptr().add( index * 8 ) as *mut __m128i, low );
+        _mm_storeu_si128( output.as_mut_ptr().add( index * 8 + 4 ) as *mut __m128i, high );
+    }
+
+    for index in chunks * 4..input.len() {
+        output[ index * 2 ] = input[ index ];
+        output[ index * 2 + 1 ] = input[ index ];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::{Argb8888, Rgb565};
+
+    fn reference< P: Pixel >( input: &Frame< P >, factor: u32 ) -> Frame< P > {
+        let mut output = Frame::new( input.width() * factor, input.height() * factor );
+        for y in 0..output.height() {
+            for x in 0..output.width() {
+                output[ (x, y) ] = input[ (x / factor, y / factor) ];
+            }
+        }
+        output
+    }
+
+    fn check< P: Pixel + PartialEq + ::std::fmt::Debug, F: Fn( u32 ) -> P >( pixel: F ) {
+        for &width in &[1, 7, 9, 17] {
+            for &factor in &[2, 3] {
+                let mut input = Frame::with_pitch( width, 3, width + 1 );
+                for y in 0..3 {
+                    for x in 0..width {
+                        input[ (x, y) ] = pixel( y * 100 + x + 1 );
+                    }
+                }
+
+                let expected = reference( &input, factor );
+                assert_eq!( scale( &input, factor ).pixels(), expected.pixels(), "width = {}, factor = {}", width, factor );
+
+                let mut output = Frame::with_pitch( width * factor, 3 * factor, width * factor + 3 );
+                scale_into( &input, &mut output, factor );
+                for y in 0..output.height() {
+                    assert_eq!( output.row( y ), expected.row( y ), "width = {}, factor = {}", width, factor );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scale_u16_pixels() {
+        check( |value| Rgb565( value as u16 ) );
+    }
+
+    #[test]
+    fn scale_u32_pixels() {
+        check( |value| Argb8888( value | 0xAB000000 ) );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse2_matches_the_scalar_path() {
+        for &width in &[1, 7, 9, 17] {
+            let input: Vec< u32 > = (0..width).map( |index| 0x01020304 * (index + 1) ).collect();
+            let expected: Vec< u32 > = input.iter().flat_map( |&value| vec![ value, value ] ).collect();
+
+            let mut output = vec![ 0; width as usize * 2 ];
+            unsafe { double_row_u32( &input, &mut output ) };
+            assert_eq!( output, expected, "width = {}", width );
+
+            let input: Vec< u16 > = input.iter().map( |&value| value as u16 ).collect();
+            let expected: Vec< u16 > = expected.iter().map( |&value| value as u16 ).collect();
+            let mut output = vec![ 0; width as usize * 2 ];
+            unsafe { double_row_u16( &input, &mut output ) };
+            assert_eq!( output, expected, "width = {}", width );
+        }
+    }
+}
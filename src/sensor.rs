@@ -0,0 +1,113 @@
+use libc;
+use libretro_sys;
+
+use environment::call_environment_mut;
+
+const SENSOR_ACCELEROMETER_ENABLE: libc::c_uint = 0;
+const SENSOR_ACCELEROMETER_DISABLE: libc::c_uint = 1;
+const SENSOR_GYROSCOPE_ENABLE: libc::c_uint = 2;
+const SENSOR_GYROSCOPE_DISABLE: libc::c_uint = 3;
+const SENSOR_ILLUMINANCE_ENABLE: libc::c_uint = 4;
+const SENSOR_ILLUMINANCE_DISABLE: libc::c_uint = 5;
+
+const SENSOR_ACCELEROMETER_X: libc::c_uint = 0;
+const SENSOR_ACCELEROMETER_Y: libc::c_uint = 1;
+const SENSOR_ACCELEROMETER_Z: libc::c_uint = 2;
+const SENSOR_GYROSCOPE_X: libc::c_uint = 3;
+const SENSOR_GYROSCOPE_Y: libc::c_uint = 4;
+const SENSOR_GYROSCOPE_Z: libc::c_uint = 5;
+const SENSOR_ILLUMINANCE: libc::c_uint = 6;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RawSensorInterface {
+    set_sensor_state: Option< unsafe extern "C" fn( port: libc::c_uint, action: libc::c_uint, rate: libc::c_uint ) -> bool >,
+    get_sensor_input: Option< unsafe extern "C" fn( port: libc::c_uint, id: libc::c_uint ) -> f32 >
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Sensor {
+    Accelerometer,
+    Gyroscope,
+    Illuminance
+}
+
+#[derive(Copy, Clone)]
+pub struct Sensors {
+    interface: RawSensorInterface
+}
+
+impl Sensors {
+    pub(crate) fn query() -> Option< Sensors > {
+        let mut interface = RawSensorInterface {
+            set_sensor_state: None,
+            get_sensor_input: None
+        };
+
+        unsafe {
+            if call_environment_mut( libretro_sys::ENVIRONMENT_GET_SENSOR_INTERFACE, &mut interface ).is_err() {
+                return None;
+            }
+        }
+
+        if interface.set_sensor_state.is_none() || interface.get_sensor_input.is_none() {
+            return None;
+        }
+
+        Some( Sensors {
+            interface: interface
+        })
+    }
+
+    fn set_state( &self, port: u32, action: libc::c_uint, rate: u32 ) -> bool {
+        unsafe {
+            (self.interface.set_sensor_state.unwrap())( port as libc::c_uint, action, rate as libc::c_uint )
+        }
+    }
+
+    fn input( &self, port: u32, id: libc::c_uint ) -> f32 {
+        unsafe {
+            (self.interface.get_sensor_input.unwrap())( port as libc::c_uint, id )
+        }
+    }
+
+    pub fn enable( &self, port: u32, sensor: Sensor, rate: u32 ) -> bool {
+        let action = match sensor {
+            Sensor::Accelerometer => SENSOR_ACCELEROMETER_ENABLE,
+            Sensor::Gyroscope => SENSOR_GYROSCOPE_ENABLE,
+            Sensor::Illuminance => SENSOR_ILLUMINANCE_ENABLE
+        };
+
+        self.set_state( port, action, rate )
+    }
+
+    pub fn disable( &self, port: u32, sensor: Sensor ) -> bool {
+        let action = match sensor {
+            Sensor::Accelerometer => SENSOR_ACCELEROMETER_DISABLE,
+            Sensor::Gyroscope => SENSOR_GYROSCOPE_DISABLE,
+            Sensor::Illuminance => SENSOR_ILLUMINANCE_DISABLE
+        };
+
+        self.set_state( port, action, 0 )
+    }
+
+    pub fn accelerometer( &self, port: u32 ) -> (f32, f32, f32) {
+        (
+            self.input( port, SENSOR_ACCELEROMETER_X ),
+            self.input( port, SENSOR_ACCELEROMETER_Y ),
+            self.input( port, SENSOR_ACCELEROMETER_Z )
+        )
+    }
+
+    pub fn gyroscope( &self, port: u32 ) -> (f32, f32, f32) {
+        (
+            self.input( port, SENSOR_GYROSCOPE_X ),
+            self.input( port, SENSOR_GYROSCOPE_Y ),
+            self.input( port, SENSOR_GYROSCOPE_Z )
+        )
+    }
+
+    pub fn illuminance( &self, port: u32 ) -> f32 {
+        self.input( port, SENSOR_ILLUMINANCE )
+    }
+}
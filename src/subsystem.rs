@@ -0,0 +1,150 @@
+use std::ffi::CString;
+use std::ptr;
+
+use libc;
+use libretro_sys;
+
+use environment::call_environment;
+
+#[repr(C)]
+struct RawSubsystemRomInfo {
+    description: *const libc::c_char,
+    valid_extensions: *const libc::c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+    required: bool,
+    memory: *const libc::c_void,
+    num_memory: libc::c_uint
+}
+
+#[repr(C)]
+struct RawSubsystemInfo {
+    description: *const libc::c_char,
+    identifier: *const libc::c_char,
+    roms: *const RawSubsystemRomInfo,
+    num_roms: libc::c_uint,
+    id: libc::c_uint
+}
+
+pub struct SubsystemRom {
+    description: CString,
+    extensions: CString,
+    needs_full_path: bool,
+    block_extract: bool,
+    is_required: bool
+}
+
+impl SubsystemRom {
+    pub fn new( description: &str ) -> SubsystemRom {
+        SubsystemRom {
+            description: CString::new( description ).unwrap(),
+            extensions: CString::new( "" ).unwrap(),
+            needs_full_path: false,
+            block_extract: false,
+            is_required: false
+        }
+    }
+
+    // A `|` separated list, e.g. "gb|gbc".
+    pub fn extensions( mut self, extensions: &str ) -> Self {
+        self.extensions = CString::new( extensions ).unwrap();
+        self
+    }
+
+    pub fn needs_full_path( mut self ) -> Self {
+        self.needs_full_path = true;
+        self
+    }
+
+    pub fn block_extract( mut self ) -> Self {
+        self.block_extract = true;
+        self
+    }
+
+    pub fn required( mut self ) -> Self {
+        self.is_required = true;
+        self
+    }
+}
+
+pub struct Subsystem {
+    id: u32,
+    identifier: CString,
+    description: CString,
+    roms: Vec< SubsystemRom >,
+    raw: Vec< RawSubsystemRomInfo >
+}
+
+impl Subsystem {
+    // The `id` is what gets passed to `Core::on_load_game_special`.
+    pub fn new( id: u32, identifier: &str, description: &str ) -> Subsystem {
+        Subsystem {
+            id: id,
+            identifier: CString::new( identifier ).unwrap(),
+            description: CString::new( description ).unwrap(),
+            roms: Vec::new(),
+            raw: Vec::new()
+        }
+    }
+
+    pub fn rom( mut self, rom: SubsystemRom ) -> Self {
+        self.raw.push( RawSubsystemRomInfo {
+            description: rom.description.as_ptr(),
+            valid_extensions: rom.extensions.as_ptr(),
+            need_fullpath: rom.needs_full_path,
+            block_extract: rom.block_extract,
+            required: rom.is_required,
+            memory: ptr::null(),
+            num_memory: 0
+        });
+        self.roms.push( rom );
+        self
+    }
+}
+
+pub struct Subsystems {
+    subsystems: Vec< Subsystem >,
+
+    // Same as with the controller info the frontend
+    // holds on to these, so they're built up front.
+    raw: Vec< RawSubsystemInfo >
+}
+
+impl Subsystems {
+    pub fn new() -> Subsystems {
+        Subsystems {
+            subsystems: Vec::new(),
+            raw: vec![ RawSubsystemInfo {
+                description: ptr::null(),
+                identifier: ptr::null(),
+                roms: ptr::null(),
+                num_roms: 0,
+                id: 0
+            }]
+        }
+    }
+
+    pub fn subsystem( mut self, subsystem: Subsystem ) -> Self {
+        let terminator = self.raw.pop().unwrap();
+        self.raw.push( RawSubsystemInfo {
+            description: subsystem.description.as_ptr(),
+            identifier: subsystem.identifier.as_ptr(),
+            roms: subsystem.raw.as_ptr(),
+            num_roms: subsystem.raw.len() as libc::c_uint,
+            id: subsystem.id as libc::c_uint
+        });
+        self.raw.push( terminator );
+        self.subsystems.push( subsystem );
+        self
+    }
+
+    pub(crate) fn register( &self ) {
+        if self.subsystems.is_empty() {
+            return;
+        }
+
+        unsafe {
+            let _ = call_environment( libretro_sys::ENVIRONMENT_SET_SUBSYSTEM_INFO, &self.raw[ 0 ] );
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::cmp::max;
+use std::ffi::{CStr, CString};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::ptr;
+
+use libc;
+use libretro_sys;
+
+use environment::call_environment_mut;
+use interfaces;
+use lock;
+
+pub use filesystem::{Metadata, OpenMode};
+
+const ENVIRONMENT_GET_VFS_INTERFACE: libc::c_uint = 45 | libretro_sys::ENVIRONMENT_EXPERIMENTAL;
+
+const VFS_FILE_ACCESS_READ: libc::c_uint = 1 << 0;
+const VFS_FILE_ACCESS_WRITE: libc::c_uint = 1 << 1;
+const VFS_FILE_ACCESS_UPDATE_EXISTING: libc::c_uint = 1 << 2;
+const VFS_FILE_ACCESS_HINT_NONE: libc::c_uint = 0;
+
+const VFS_STAT_IS_VALID: libc::c_int = 1 << 0;
+const VFS_STAT_IS_DIRECTORY: libc::c_int = 1 << 1;
+const VFS_STAT_IS_CHARACTER_SPECIAL: libc::c_int = 1 << 2;
+
+const VFS_MKDIR_ALREADY_EXISTS: libc::c_int = -2;
+
+const VFS_SEEK_POSITION_START: libc::c_int = 0;
+const VFS_SEEK_POSITION_CURRENT: libc::c_int = 1;
+const VFS_SEEK_POSITION_END: libc::c_int = 2;
+
+enum RawFileHandle {}
+
+#[repr(C)]
+struct RawVfsInterface {
+    get_path: Option< unsafe extern "C" fn( stream: *mut RawFileHandle ) -> *const libc::c_char >,
+    open: Option< unsafe extern "C" fn( path: *const libc::c_char, mode: libc::c_uint, hints: libc::c_uint ) -> *mut RawFileHandle >,
+    close: Option< unsafe extern "C" fn( stream: *mut RawFileHandle ) -> libc::c_int >,
+    size: Option< unsafe extern "C" fn( stream: *mut RawFileHandle ) -> i64 >,
+    tell: Option< unsafe extern "C" fn( stream: *mut RawFileHandle ) -> i64 >,
+    seek: Option< unsafe extern "C" fn( stream: *mut RawFileHandle, offset: i64, seek_position: libc::c_int ) -> i64 >,
+    read: Option< unsafe extern "C" fn( stream: *mut RawFileHandle, buffer: *mut libc::c_void, length: u64 ) -> i64 >,
+    write: Option< unsafe extern "C" fn( stream: *mut RawFileHandle, buffer: *const libc::c_void, length: u64 ) -> i64 >,
+    flush: Option< unsafe extern "C" fn( stream: *mut RawFileHandle ) -> libc::c_int >,
+    remove: Option< unsafe extern "C" fn( path: *const libc::c_char ) -> libc::c_int >,
+    rename: Option< unsafe extern "C" fn( old_path: *const libc::c_char, new_path: *const libc::c_char ) -> libc::c_int >,
+    // Version 2.
+    truncate: Option< unsafe extern "C" fn( stream: *mut RawFileHandle, length: i64 ) -> i64 >,
+    // Version 3.
+    stat: Option< unsafe extern "C" fn( path: *const libc::c_char, size: *mut i32 ) -> libc::c_int >,
+    mkdir: Option< unsafe extern "C" fn( directory: *const libc::c_char ) -> libc::c_int >
+}
+
+#[repr(C)]
+struct RawVfsInterfaceInfo {
+    required_interface_version: u32,
+    interface: *const RawVfsInterface
+}
+
+#[derive(Copy, Clone)]
+pub struct Vfs {
+    interface: &'static RawVfsInterface,
+    version: u32
+}
+
+pub(crate) fn query() {
+    let mut vfs = None;
+    for version in (1..4).rev() {
+        let mut info = RawVfsInterfaceInfo {
+            required_interface_version: version,
+            interface: ptr::null()
+        };
+
+        let result = unsafe { call_environment_mut( ENVIRONMENT_GET_VFS_INTERFACE, &mut info ) };
+        if result.is_ok() && info.interface != ptr::null() {
+            vfs = Some( Vfs {
+                interface: unsafe { &*info.interface },
+                version: info.required_interface_version
+            });
+            break;
+        }
+    }
+
+    interfaces::with( |interfaces| *lock( &interfaces.vfs ) = vfs );
+}
+
+// Returns `None` if the frontend doesn't provide a virtual file system,
+// in which case the core is free to use `std::fs` directly.
+pub fn get() -> Option< Vfs > {
+    interfaces::with( |interfaces| *lock( &interfaces.vfs ) )
+}
+
+fn path_to_cstring( path: &Path ) -> io::Result< CString > {
+    let path = path.to_str().ok_or_else( || io::Error::new( io::ErrorKind::InvalidInput, "path is not valid UTF-8" ) )?;
+    CString::new( path ).map_err( |_| io::Error::new( io::ErrorKind::InvalidInput, "path contains a NUL byte" ) )
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new( io::ErrorKind::Other, "operation not supported by the frontend's VFS" )
+}
+
+fn failed() -> io::Error {
+    io::Error::new( io::ErrorKind::Other, "VFS operation failed" )
+}
+
+impl Vfs {
+    pub fn version( &self ) -> u32 {
+        self.version
+    }
+
+    pub fn open< P: AsRef< Path > >( &self, path: P, mode: OpenMode ) -> io::Result< VfsFile > {
+        let open = self.interface.open.ok_or_else( unsupported )?;
+        let path = path_to_cstring( path.as_ref() )?;
+        let mode = match mode {
+            OpenMode::Read => VFS_FILE_ACCESS_READ,
+            OpenMode::Write => VFS_FILE_ACCESS_WRITE,
+            OpenMode::ReadWrite => VFS_FILE_ACCESS_READ | VFS_FILE_ACCESS_WRITE,
+            OpenMode::Update => VFS_FILE_ACCESS_READ | VFS_FILE_ACCESS_WRITE | VFS_FILE_ACCESS_UPDATE_EXISTING
+        };
+
+        let handle = unsafe { open( path.as_ptr(), mode, VFS_FILE_ACCESS_HINT_NONE ) };
+        if handle == ptr::null_mut() {
+            return Err( io::Error::new( io::ErrorKind::NotFound, "failed to open the file through the VFS" ) );
+        }
+
+        Ok( VfsFile {
+            vfs: *self,
+            handle: handle
+        })
+    }
+
+    pub fn remove< P: AsRef< Path > >( &self, path: P ) -> io::Result< () > {
+        let remove = self.interface.remove.ok_or_else( unsupported )?;
+        let path = path_to_cstring( path.as_ref() )?;
+        if unsafe { remove( path.as_ptr() ) } != 0 {
+            return Err( failed() );
+        }
+
+        Ok(())
+    }
+
+    pub fn stat< P: AsRef< Path > >( &self, path: P ) -> io::Result< Metadata > {
+        if self.version < 3 {
+            return Err( unsupported() );
+        }
+
+        let stat = self.interface.stat.ok_or_else( unsupported )?;
+        let path = path_to_cstring( path.as_ref() )?;
+        let mut size: i32 = 0;
+        let flags = unsafe { stat( path.as_ptr(), &mut size ) };
+        if flags & VFS_STAT_IS_VALID == 0 {
+            return Err( io::Error::new( io::ErrorKind::NotFound, "no such file or directory" ) );
+        }
+
+        Ok( Metadata {
+            size: max( size, 0 ) as u64,
+            is_directory: flags & VFS_STAT_IS_DIRECTORY != 0,
+            is_character_special: flags & VFS_STAT_IS_CHARACTER_SPECIAL != 0
+        })
+    }
+
+    pub fn mkdir< P: AsRef< Path > >( &self, path: P ) -> io::Result< () > {
+        if self.version < 3 {
+            return Err( unsupported() );
+        }
+
+        let mkdir = self.interface.mkdir.ok_or_else( unsupported )?;
+        let path = path_to_cstring( path.as_ref() )?;
+        match unsafe { mkdir( path.as_ptr() ) } {
+            0 => Ok(()),
+            VFS_MKDIR_ALREADY_EXISTS => Err( io::Error::new( io::ErrorKind::AlreadyExists, "the directory already exists" ) ),
+            _ => Err( failed() )
+        }
+    }
+
+    pub fn rename< P: AsRef< Path >, Q: AsRef< Path > >( &self, old_path: P, new_path: Q ) -> io::Result< () > {
+        let rename = self.interface.rename.ok_or_else( unsupported )?;
+        let old_path = path_to_cstring( old_path.as_ref() )?;
+        let new_path = path_to_cstring( new_path.as_ref() )?;
+        if unsafe { rename( old_path.as_ptr(), new_path.as_ptr() ) } != 0 {
+            return Err( failed() );
+        }
+
+        Ok(())
+    }
+}
+
+pub struct VfsFile {
+    vfs: Vfs,
+    handle: *mut RawFileHandle
+}
+
+impl VfsFile {
+    pub fn path( &self ) -> Option< String > {
+        let get_path = self.vfs.interface.get_path?;
+        unsafe {
+            let path = get_path( self.handle );
+            if path == ptr::null() {
+                return None;
+            }
+
+            CStr::from_ptr( path ).to_str().ok().map( |path| path.to_owned() )
+        }
+    }
+
+    pub fn size( &self ) -> io::Result< u64 > {
+        let size = self.vfs.interface.size.ok_or_else( unsupported )?;
+        let result = unsafe { size( self.handle ) };
+        if result < 0 {
+            return Err( failed() );
+        }
+
+        Ok( result as u64 )
+    }
+
+    pub fn set_len( &mut self, length: u64 ) -> io::Result< () > {
+        if self.vfs.version < 2 {
+            return Err( unsupported() );
+        }
+
+        let truncate = self.vfs.interface.truncate.ok_or_else( unsupported )?;
+        if unsafe { truncate( self.handle, length as i64 ) } != 0 {
+            return Err( failed() );
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for VfsFile {
+    fn read( &mut self, buffer: &mut [u8] ) -> io::Result< usize > {
+        let read = self.vfs.interface.read.ok_or_else( unsupported )?;
+        let result = unsafe { read( self.handle, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len() as u64 ) };
+        if result < 0 {
+            return Err( failed() );
+        }
+
+        Ok( result as usize )
+    }
+}
+
+impl Write for VfsFile {
+    fn write( &mut self, buffer: &[u8] ) -> io::Result< usize > {
+        let write = self.vfs.interface.write.ok_or_else( unsupported )?;
+        let result = unsafe { write( self.handle, buffer.as_ptr() as *const libc::c_void, buffer.len() as u64 ) };
+        if result < 0 {
+            return Err( failed() );
+        }
+
+        Ok( result as usize )
+    }
+
+    fn flush( &mut self ) -> io::Result< () > {
+        let flush = self.vfs.interface.flush.ok_or_else( unsupported )?;
+        if unsafe { flush( self.handle ) } != 0 {
+            return Err( failed() );
+        }
+
+        Ok(())
+    }
+}
+
+impl Seek for VfsFile {
+    fn seek( &mut self, position: SeekFrom ) -> io::Result< u64 > {
+        let seek = self.vfs.interface.seek.ok_or_else( unsupported )?;
+        let (offset, whence) = match position {
+            SeekFrom::Start( offset ) => (offset as i64, VFS_SEEK_POSITION_START),
+            SeekFrom::Current( offset ) => (offset, VFS_SEEK_POSITION_CURRENT),
+            SeekFrom::End( offset ) => (offset, VFS_SEEK_POSITION_END)
+        };
+
+        let result = unsafe { seek( self.handle, offset, whence ) };
+        if result < 0 {
+            return Err( failed() );
+        }
+
+        // Some frontends return 0 instead of the new position.
+        match self.vfs.interface.tell {
+            Some( tell ) => {
+                let position = unsafe { tell( self.handle ) };
+                if position < 0 {
+                    return Err( failed() );
+                }
+
+                Ok( position as u64 )
+            },
+            None => Ok( result as u64 )
+        }
+    }
+}
+
+impl Drop for VfsFile {
+    fn drop( &mut self ) {
+        if let Some( close ) = self.vfs.interface.close {
+            unsafe {
+                close( self.handle );
+            }
+        }
+    }
+}
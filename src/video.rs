@@ -0,0 +1,489 @@
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use libc;
+use libretro_sys::{self, PixelFormat};
+
+use environment::{call_environment, call_environment_mut, EnvError};
+use frame::{self, Frame, Pixel, SurfaceMut};
+use {AudioVideoInfo, RuntimeHandle, UploadError};
+
+#[cfg(feature = "hw-render")]
+const HW_FRAME_BUFFER_VALID: *const libc::c_void = -1_isize as *const libc::c_void;
+const MEMORY_ACCESS_WRITE: libc::c_uint = 1;
+
+#[repr(C)]
+struct RawFrameBuffer {
+    data: *mut libc::c_void,
+    width: libc::c_uint,
+    height: libc::c_uint,
+    pitch: libc::size_t,
+    format: PixelFormat,
+    access_flags: libc::c_uint,
+    memory_flags: libc::c_uint
+}
+
+// The number of bytes a frame spans; the last scanline doesn't need to be padded.
+fn frame_length( bytes_per_line: usize, height: u32, pitch: usize ) -> Option< usize > {
+    if height == 0 {
+        return Some( 0 );
+    }
+
+    pitch.checked_mul( height as usize - 1 )?.checked_add( bytes_per_line )
+}
+
+// Tries the core's own pixel format first and then falls back to the others,
+// in the order of how much gets lost when converting to them.
+pub(crate) fn negotiate_pixel_format( pixel_format: PixelFormat ) -> Option< PixelFormat > {
+    let fallbacks = [PixelFormat::ARGB8888, PixelFormat::RGB565, PixelFormat::ARGB1555];
+    let candidates = Some( pixel_format ).into_iter().chain( fallbacks.iter().cloned().filter( |&format| format != pixel_format ) );
+    for candidate in candidates {
+        if unsafe { call_environment( libretro_sys::ENVIRONMENT_SET_PIXEL_FORMAT, &candidate ) }.is_ok() {
+            return Some( candidate );
+        }
+    }
+
+    None
+}
+
+enum FrameBufferStorage {
+    Frontend( *mut u8 ),
+    Fallback( Vec< u8 > )
+}
+
+// A buffer the core can render the current frame directly into. When the
+// frontend can't provide one of its own this is backed by a buffer owned
+// by us which is uploaded through the usual copying path.
+pub struct FrontendFrameBuffer< 'a > {
+    handle: &'a mut RuntimeHandle,
+    storage: FrameBufferStorage,
+    width: u32,
+    height: u32,
+    pitch: usize
+}
+
+impl< 'a > FrontendFrameBuffer< 'a > {
+    pub fn width( &self ) -> u32 {
+        self.width
+    }
+
+    pub fn height( &self ) -> u32 {
+        self.height
+    }
+
+    // In bytes.
+    pub fn pitch( &self ) -> usize {
+        self.pitch
+    }
+
+    pub fn pixel_format( &self ) -> PixelFormat {
+        self.handle.video_pixel_format
+    }
+
+    pub fn is_frontend_memory( &self ) -> bool {
+        match self.storage {
+            FrameBufferStorage::Frontend( _ ) => true,
+            FrameBufferStorage::Fallback( _ ) => false
+        }
+    }
+
+    pub fn data_mut( &mut self ) -> &mut [u8] {
+        let length = self.pitch * self.height as usize;
+        match self.storage {
+            FrameBufferStorage::Frontend( pointer ) => unsafe { slice::from_raw_parts_mut( pointer, length ) },
+            FrameBufferStorage::Fallback( ref mut buffer ) => &mut buffer[ ..length ]
+        }
+    }
+
+    // A typed view of the buffer; `None` if the pixel type doesn't match
+    // the current pixel format or the buffer isn't suitably aligned for it.
+    pub fn surface< 'b, P: Pixel >( &'b mut self ) -> Option< SurfaceMut< 'b, P > > {
+        let size = mem::size_of::< P >();
+        if P::FORMAT != self.pixel_format() || self.pitch % size != 0 {
+            return None;
+        }
+
+        let pitch = self.pitch / size;
+        let width = self.width;
+        let height = self.height;
+        let data = self.data_mut();
+        if data.as_ptr() as usize % mem::align_of::< P >() != 0 {
+            return None;
+        }
+
+        let pixels = unsafe { slice::from_raw_parts_mut( data.as_mut_ptr() as *mut P, data.len() / size ) };
+        Some( SurfaceMut::new( pixels, width, height, pitch as u32 ) )
+    }
+
+    // Can't fail since `RuntimeHandle::frame_buffer` only
+    // hands out a buffer if nothing was uploaded yet.
+    pub fn present( self ) {
+        self.handle.upload_video_frame_already_called = true;
+        let pointer = match self.storage {
+            FrameBufferStorage::Frontend( pointer ) => pointer as *const u8,
+            FrameBufferStorage::Fallback( ref buffer ) => buffer.as_ptr()
+        };
+
+        unsafe {
+            self.handle.submit_video_frame( pointer, self.width, self.height, self.pitch );
+        }
+    }
+}
+
+impl< 'a > Drop for FrontendFrameBuffer< 'a > {
+    fn drop( &mut self ) {
+        if let FrameBufferStorage::Fallback( ref mut buffer ) = self.storage {
+            self.handle.fallback_frame_buffer = mem::replace( buffer, Vec::new() );
+        }
+    }
+}
+
+impl RuntimeHandle {
+    pub fn upload_video_frame( &mut self, data: &[u8] ) -> Result< (), UploadError > {
+        let pitch = (self.video_width * self.video_frame_bytes_per_pixel) as usize;
+        self.upload_video_frame_with_pitch( data, pitch )
+    }
+
+    // For the RGB565 and ARGB1555 pixel formats.
+    pub fn upload_video_frame_u16( &mut self, data: &[u16] ) -> Result< (), UploadError > {
+        if self.video_frame_bytes_per_pixel != 2 {
+            return Err( UploadError::PixelFormatMismatch );
+        }
+
+        let bytes = unsafe { slice::from_raw_parts( data.as_ptr() as *const u8, data.len() * 2 ) };
+        self.upload_video_frame( bytes )
+    }
+
+    // For the ARGB8888 pixel format.
+    pub fn upload_video_frame_u32( &mut self, data: &[u32] ) -> Result< (), UploadError > {
+        if self.video_frame_bytes_per_pixel != 4 {
+            return Err( UploadError::PixelFormatMismatch );
+        }
+
+        let bytes = unsafe { slice::from_raw_parts( data.as_ptr() as *const u8, data.len() * 4 ) };
+        self.upload_video_frame( bytes )
+    }
+
+    // For frame buffers with padding at the end of every scanline;
+    // `pitch` is the distance between the scanlines in bytes; one which is
+    // too big to be backed by `data` fails with `UploadError::DataTooSmall`.
+    pub fn upload_video_frame_with_pitch( &mut self, data: &[u8], pitch: usize ) -> Result< (), UploadError > {
+        let width = self.video_width;
+        let height = self.video_height;
+        self.upload_video_frame_with_size( data, width, height, pitch )
+    }
+
+    // Uploads a frame with a different size than the current geometry, e.g. when
+    // the emulated system switches to a high resolution mode for a few frames.
+    // The size can't be bigger than the maximum size from `AudioVideoInfo`.
+    pub fn upload_video_frame_with_size( &mut self, data: &[u8], width: u32, height: u32, pitch: usize ) -> Result< (), UploadError > {
+        if self.upload_video_frame_already_called {
+            return Err( UploadError::AlreadyUploaded );
+        }
+
+        if width > self.video_max_width || height > self.video_max_height {
+            return Err( UploadError::FrameTooLarge );
+        }
+
+        let bytes_per_line = (width * self.video_frame_bytes_per_pixel) as usize;
+        if pitch < bytes_per_line {
+            return Err( UploadError::PitchTooSmall );
+        }
+
+        // A length which doesn't fit in a `usize` can't be backed by any slice.
+        match frame_length( bytes_per_line, height, pitch ) {
+            Some( length ) if data.len() >= length => {},
+            _ => return Err( UploadError::DataTooSmall )
+        }
+
+        self.upload_video_frame_already_called = true;
+        unsafe {
+            self.submit_video_frame( data.as_ptr(), width, height, pitch );
+        }
+
+        Ok(())
+    }
+
+    // The data has to cover the whole frame.
+    unsafe fn submit_video_frame( &mut self, data: *const u8, width: u32, height: u32, pitch: usize ) {
+        if self.video_output_pixel_format == self.video_pixel_format || height == 0 {
+            (self.video_refresh_callback)( data as *const libc::c_void, width as libc::c_uint, height as libc::c_uint, pitch );
+            return;
+        }
+
+        let length = match frame_length( (width * self.video_frame_bytes_per_pixel) as usize, height, pitch ) {
+            Some( length ) => length,
+            None => return
+        };
+
+        let input = slice::from_raw_parts( data, length );
+        let output_pitch = frame::convert( input, self.video_pixel_format, pitch, &mut self.conversion_buffer, self.video_output_pixel_format, width, height );
+        (self.video_refresh_callback)( self.conversion_buffer.as_ptr() as *const libc::c_void, width as libc::c_uint, height as libc::c_uint, output_pitch );
+    }
+
+    pub fn upload_frame< P: Pixel >( &mut self, frame: &Frame< P > ) -> Result< (), UploadError > {
+        if P::FORMAT != self.video_pixel_format {
+            return Err( UploadError::PixelFormatMismatch );
+        }
+
+        let pitch = frame.pitch() as usize * mem::size_of::< P >();
+        self.upload_video_frame_with_size( frame.as_bytes(), frame.width(), frame.height(), pitch )
+    }
+
+    // Converts the image into whatever pixel format the frontend has accepted.
+    #[cfg(feature = "image")]
+    pub fn upload_image< P, C >( &mut self, image: &image::ImageBuffer< P, C > ) -> Result< (), UploadError >
+        where P: image::Pixel< Subpixel = u8 >, C: std::ops::Deref< Target = [u8] >
+    {
+        if self.upload_video_frame_already_called {
+            return Err( UploadError::AlreadyUploaded );
+        }
+
+        let (width, height) = image.dimensions();
+        if width > self.video_max_width || height > self.video_max_height {
+            return Err( UploadError::FrameTooLarge );
+        }
+
+        let format = self.video_output_pixel_format;
+        let bytes_per_pixel = frame::bytes_per_pixel( format );
+        let pitch = width as usize * bytes_per_pixel;
+        self.conversion_buffer.clear();
+        self.conversion_buffer.resize( pitch * height as usize, 0 );
+        for (pixel, output) in image.pixels().zip( self.conversion_buffer.chunks_mut( bytes_per_pixel ) ) {
+            let rgb = pixel.to_rgb();
+            frame::write_pixel( format, (rgb[ 0 ], rgb[ 1 ], rgb[ 2 ]), output );
+        }
+
+        self.upload_video_frame_already_called = true;
+        unsafe {
+            (self.video_refresh_callback)( self.conversion_buffer.as_ptr() as *const libc::c_void, width as libc::c_uint, height as libc::c_uint, pitch );
+        }
+
+        Ok(())
+    }
+
+    pub fn frame_buffer< 'a >( &'a mut self ) -> Option< FrontendFrameBuffer< 'a > > {
+        if self.upload_video_frame_already_called {
+            return None;
+        }
+
+        let width = self.video_width;
+        let height = self.video_height;
+        let mut frame_buffer = RawFrameBuffer {
+            data: ptr::null_mut(),
+            width: width as libc::c_uint,
+            height: height as libc::c_uint,
+            pitch: 0,
+            format: self.video_pixel_format,
+            access_flags: MEMORY_ACCESS_WRITE,
+            memory_flags: 0
+        };
+
+        let result = unsafe {
+            call_environment_mut( libretro_sys::ENVIRONMENT_GET_CURRENT_SOFTWARE_FRAMEBUFFER, &mut frame_buffer )
+        };
+
+        if result.is_ok() &&
+           self.video_output_pixel_format == self.video_pixel_format &&
+           frame_buffer.data != ptr::null_mut() &&
+           frame_buffer.format == self.video_pixel_format &&
+           frame_buffer.width == width as libc::c_uint &&
+           frame_buffer.height == height as libc::c_uint {
+            return Some( FrontendFrameBuffer {
+                handle: self,
+                storage: FrameBufferStorage::Frontend( frame_buffer.data as *mut u8 ),
+                width: width,
+                height: height,
+                pitch: frame_buffer.pitch
+            });
+        }
+
+        let pitch = (width * self.video_frame_bytes_per_pixel) as usize;
+        let mut buffer = mem::replace( &mut self.fallback_frame_buffer, Vec::new() );
+        buffer.resize( pitch * height as usize, 0 );
+
+        Some( FrontendFrameBuffer {
+            handle: self,
+            storage: FrameBufferStorage::Fallback( buffer ),
+            width: width,
+            height: height,
+            pitch: pitch
+        })
+    }
+
+    // Changes the size of the frames uploaded from now on. The new size
+    // can't exceed the maximum size declared in the `AudioVideoInfo`.
+    pub fn set_geometry( &mut self, width: u32, height: u32, aspect_ratio: Option< f32 > ) -> Result< (), EnvError > {
+        if width > self.video_max_width || height > self.video_max_height {
+            return Err( EnvError::InvalidArgument );
+        }
+
+        let geometry = libretro_sys::GameGeometry {
+            base_width: width as libc::c_uint,
+            base_height: height as libc::c_uint,
+            max_width: self.video_max_width as libc::c_uint,
+            max_height: self.video_max_height as libc::c_uint,
+            aspect_ratio: aspect_ratio.unwrap_or( 0.0 )
+        };
+
+        unsafe {
+            call_environment( libretro_sys::ENVIRONMENT_SET_GEOMETRY, &geometry )?;
+        }
+
+        self.video_width = width;
+        self.video_height = height;
+        self.video_aspect_ratio = aspect_ratio;
+        Ok(())
+    }
+
+    // Changes the timing and the geometry of the running game; the frontend
+    // might reinitialize its drivers in response, so this should be rare.
+    // Neither the pixel format nor the hardware context can be changed.
+    pub fn set_av_info( &mut self, av_info: AudioVideoInfo ) -> Result< (), EnvError > {
+        if av_info.pixel_format != self.video_pixel_format {
+            return Err( EnvError::InvalidArgument );
+        }
+
+        #[cfg(feature = "hw-render")]
+        {
+            if av_info.hardware_context.is_some() {
+                return Err( EnvError::InvalidArgument );
+            }
+        }
+
+        let info = av_info.to_raw();
+        unsafe {
+            call_environment( libretro_sys::ENVIRONMENT_SET_SYSTEM_AV_INFO, &info )?;
+        }
+
+        self.video_width = av_info.width;
+        self.video_height = av_info.height;
+        self.video_max_width = av_info.max_width;
+        self.video_max_height = av_info.max_height;
+        self.video_aspect_ratio = av_info.aspect_ratio;
+        self.new_av_info = Some( av_info );
+        Ok(())
+    }
+
+    pub fn can_dupe_video_frame( &self ) -> bool {
+        self.can_dupe
+    }
+
+    // Tells the frontend to show the previous frame again. Returns `false`
+    // if the frontend doesn't support this, in which case the frame has
+    // to be uploaded as usual.
+    pub fn dupe_video_frame( &mut self ) -> Result< bool, UploadError > {
+        if self.upload_video_frame_already_called {
+            return Err( UploadError::AlreadyUploaded );
+        }
+
+        if self.can_dupe == false {
+            return Ok( false );
+        }
+
+        self.upload_video_frame_already_called = true;
+        let width = self.video_width as libc::c_uint;
+        let height = self.video_height as libc::c_uint;
+        unsafe {
+            (self.video_refresh_callback)( ptr::null(), width, height, 0 );
+        }
+
+        Ok( true )
+    }
+
+    // Presents whatever the core has rendered into the frontend's
+    // framebuffer when using a hardware context.
+    #[cfg(feature = "hw-render")]
+    pub fn upload_hardware_frame( &mut self, width: u32, height: u32 ) -> Result< (), UploadError > {
+        if self.upload_video_frame_already_called {
+            return Err( UploadError::AlreadyUploaded );
+        }
+
+        self.upload_video_frame_already_called = true;
+        unsafe {
+            (self.video_refresh_callback)( HW_FRAME_BUFFER_VALID, width as libc::c_uint, height as libc::c_uint, 0 );
+        }
+
+        Ok(())
+    }
+
+    // Whether a frame was already uploaded during this call to `Core::on_run`.
+    pub fn is_video_frame_uploaded( &self ) -> bool {
+        self.upload_video_frame_already_called
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static FRAMES_SUBMITTED: AtomicUsize = AtomicUsize::new( 0 );
+
+    unsafe extern "C" fn video_refresh( _: *const libc::c_void, _: libc::c_uint, _: libc::c_uint, _: libc::size_t ) {
+        FRAMES_SUBMITTED.fetch_add( 1, Ordering::SeqCst );
+    }
+
+    unsafe extern "C" fn input_state( _: libc::c_uint, _: libc::c_uint, _: libc::c_uint, _: libc::c_uint ) -> i16 {
+        0
+    }
+
+    unsafe extern "C" fn audio_sample_batch( _: *const i16, frames: libc::size_t ) -> libc::size_t {
+        frames
+    }
+
+    fn handle( width: u32, height: u32 ) -> RuntimeHandle {
+        RuntimeHandle {
+            video_refresh_callback: video_refresh,
+            input_state_callback: input_state,
+            audio_sample_batch_callback: audio_sample_batch,
+            upload_video_frame_already_called: false,
+            audio_samples_uploaded: 0,
+            supports_input_bitmasks: false,
+            can_dupe: false,
+            max_users: 1,
+            sensors: None,
+            midi: None,
+            joypad_states: Vec::new(),
+            previous_joypad_states: Vec::new(),
+            fallback_frame_buffer: Vec::new(),
+            conversion_buffer: Vec::new(),
+            new_av_info: None,
+            audio_video_enable: 3,
+
+            video_width: width,
+            video_height: height,
+            video_max_width: width,
+            video_max_height: height,
+            video_aspect_ratio: None,
+            video_pixel_format: PixelFormat::RGB565,
+            video_output_pixel_format: PixelFormat::RGB565,
+            video_frame_bytes_per_pixel: 2
+        }
+    }
+
+    #[test]
+    fn overflowing_pitch_is_rejected() {
+        let data = [0; 64];
+
+        let mut handle = self::handle( 4, 3 );
+        assert_eq!( handle.upload_video_frame_with_pitch( &data, usize::MAX ), Err( UploadError::DataTooSmall ) );
+        assert_eq!( handle.upload_video_frame_with_size( &data, 4, 3, usize::MAX ), Err( UploadError::DataTooSmall ) );
+        assert_eq!( handle.upload_video_frame_with_size( &data, 4, 3, usize::MAX / 2 + 1 ), Err( UploadError::DataTooSmall ) );
+        assert_eq!( FRAMES_SUBMITTED.load( Ordering::SeqCst ), 0 );
+
+        // A single scanline never needs the pitch.
+        assert_eq!( handle.upload_video_frame_with_size( &data, 4, 1, usize::MAX ), Ok(()) );
+        assert_eq!( FRAMES_SUBMITTED.load( Ordering::SeqCst ), 1 );
+    }
+
+    #[test]
+    fn frame_length_doesnt_pad_the_last_scanline() {
+        assert_eq!( frame_length( 8, 0, 16 ), Some( 0 ) );
+        assert_eq!( frame_length( 8, 3, 16 ), Some( 40 ) );
+        assert_eq!( frame_length( 8, 3, usize::MAX ), None );
+        assert_eq!( frame_length( usize::MAX, 2, 1 ), None );
+    }
+}
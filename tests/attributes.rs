@@ -0,0 +1,11 @@
+extern crate trybuild;
+
+#[test]
+fn libretro_core_attribute() {
+    let tests = trybuild::TestCases::new();
+    tests.pass( "tests/ui/skip.rs" );
+    tests.compile_fail( "tests/ui/skip_unknown_entry_point.rs" );
+    tests.compile_fail( "tests/ui/skip_init.rs" );
+    tests.compile_fail( "tests/ui/unknown_argument.rs" );
+    tests.compile_fail( "tests/ui/generic_core.rs" );
+}
@@ -0,0 +1,42 @@
+extern crate libretro_backend;
+
+use libretro_backend::attributes::libretro_core;
+
+#[libretro_core( skip( retro_get_memory_data, retro_get_memory_size ) )]
+#[derive(Default)]
+struct Emulator;
+
+impl libretro_backend::Core for Emulator {
+    type Error = String;
+
+    fn info() -> libretro_backend::CoreInfo {
+        libretro_backend::CoreInfo::new( "Emulator", "0.1" )
+    }
+
+    fn on_load_game( &mut self, game_data: libretro_backend::GameData ) -> Result< libretro_backend::AudioVideoInfo, libretro_backend::LoadGameError< String > > {
+        Err( libretro_backend::LoadGameError::unsupported( game_data ) )
+    }
+
+    fn on_unload_game( &mut self ) -> libretro_backend::GameData {
+        unimplemented!()
+    }
+
+    fn on_run( &mut self, _handle: &mut libretro_backend::RuntimeHandle ) {
+    }
+
+    fn on_reset( &mut self ) {
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data( _id: libretro_backend::libc::c_uint ) -> *mut libretro_backend::libc::c_void {
+    0 as *mut _
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size( _id: libretro_backend::libc::c_uint ) -> libretro_backend::libc::size_t {
+    0
+}
+
+fn main() {
+}
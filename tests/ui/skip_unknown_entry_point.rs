@@ -0,0 +1,32 @@
+extern crate libretro_backend;
+
+use libretro_backend::attributes::libretro_core;
+
+#[libretro_core( skip( retro_frobnicate ) )]
+#[derive(Default)]
+struct Emulator;
+
+impl libretro_backend::Core for Emulator {
+    type Error = String;
+
+    fn info() -> libretro_backend::CoreInfo {
+        libretro_backend::CoreInfo::new( "Emulator", "0.1" )
+    }
+
+    fn on_load_game( &mut self, game_data: libretro_backend::GameData ) -> Result< libretro_backend::AudioVideoInfo, libretro_backend::LoadGameError< String > > {
+        Err( libretro_backend::LoadGameError::unsupported( game_data ) )
+    }
+
+    fn on_unload_game( &mut self ) -> libretro_backend::GameData {
+        unimplemented!()
+    }
+
+    fn on_run( &mut self, _handle: &mut libretro_backend::RuntimeHandle ) {
+    }
+
+    fn on_reset( &mut self ) {
+    }
+}
+
+fn main() {
+}